@@ -0,0 +1,20 @@
+use std::env;
+use std::process::Command;
+
+fn main() {
+    // Recorded so `doctor` can tell stable builds from nightly ones
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_owned())
+        .unwrap_or_else(|| "unknown rustc".to_owned());
+    println!("cargo:rustc-env=MIBAND_RUSTC_VERSION={version}");
+
+    // Used by `self-update` to pick the matching release asset
+    let target = env::var("TARGET").unwrap_or_default();
+    println!("cargo:rustc-env=MIBAND_TARGET={target}");
+    println!("cargo:rerun-if-changed=build.rs");
+}
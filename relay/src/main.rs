@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use clap::Parser;
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+const VIEWER: &str = include_str!("viewer.html");
+
+/// Relays live heart rate streams from publishers (`miband-heart-rate --relay`)
+/// to any number of viewers, so nobody needs port forwarding.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Listen address
+    #[arg(long, value_name = "ADDR", default_value = "0.0.0.0:8080")]
+    addr: SocketAddr,
+
+    /// Only accept publishers that send this key as `X-Relay-Key`
+    #[arg(long, env = "RELAY_KEY", hide_env_values = true)]
+    publish_key: Option<String>,
+}
+
+/// One live stream. The first publisher's token claims it; the channel goes
+/// away once neither a publisher nor viewers are connected.
+struct Channel {
+    token: String,
+    publishing: bool,
+    tx: broadcast::Sender<String>,
+    last: Option<String>,
+}
+
+#[derive(Clone)]
+struct Relay {
+    channels: Arc<Mutex<HashMap<String, Channel>>>,
+    publish_key: Option<String>,
+}
+
+impl Relay {
+    /// Drop `name` if nobody uses it any more.
+    fn cleanup(&self, name: &str) {
+        let mut channels = self.channels.lock().unwrap();
+        if channels
+            .get(name)
+            .is_some_and(|c| !c.publishing && c.tx.receiver_count() == 0)
+        {
+            channels.remove(name);
+        }
+    }
+}
+
+/// Marks a channel as having a publisher until dropped, which also happens
+/// when the WebSocket upgrade fails and the callback never runs.
+struct Publishing {
+    relay: Relay,
+    name: String,
+}
+
+impl Drop for Publishing {
+    fn drop(&mut self) {
+        if let Some(channel) = self.relay.channels.lock().unwrap().get_mut(&self.name) {
+            channel.publishing = false;
+        }
+        self.relay.cleanup(&self.name);
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let relay = Relay {
+        channels: Arc::default(),
+        publish_key: cli.publish_key,
+    };
+    let app = Router::new()
+        .route("/publish/{channel}", get(publish))
+        .route("/watch/{channel}", get(watch))
+        .route("/view/{channel}", get(view))
+        .with_state(relay);
+
+    let listener = TcpListener::bind(cli.addr).await?;
+    eprintln!("Relay listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+fn valid_channel(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+async fn publish(
+    State(relay): State<Relay>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    if !valid_channel(&name) {
+        return (StatusCode::BAD_REQUEST, "Invalid channel name").into_response();
+    }
+    if let Some(key) = &relay.publish_key {
+        if headers.get("x-relay-key").and_then(|v| v.to_str().ok()) != Some(key) {
+            return (StatusCode::UNAUTHORIZED, "Wrong or missing X-Relay-Key").into_response();
+        }
+    }
+    let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .filter(|token| !token.is_empty())
+    else {
+        return (StatusCode::UNAUTHORIZED, "Missing bearer token").into_response();
+    };
+
+    let tx = {
+        let mut channels = relay.channels.lock().unwrap();
+        let channel = channels.entry(name.clone()).or_insert_with(|| Channel {
+            token: token.to_owned(),
+            publishing: false,
+            tx: broadcast::channel(16).0,
+            last: None,
+        });
+        if channel.token != token {
+            return (
+                StatusCode::FORBIDDEN,
+                "Channel belongs to another publisher",
+            )
+                .into_response();
+        }
+        if channel.publishing {
+            return (StatusCode::CONFLICT, "Channel already has a publisher").into_response();
+        }
+        channel.publishing = true;
+        channel.tx.clone()
+    };
+    let publishing = Publishing {
+        relay: relay.clone(),
+        name: name.clone(),
+    };
+
+    ws.on_failed_upgrade(|err| eprintln!("Publisher upgrade failed: {err}"))
+        .on_upgrade(move |mut socket| async move {
+            let _publishing = publishing;
+            while let Some(Ok(message)) = socket.recv().await {
+                match message {
+                    Message::Text(text) => {
+                        let text = text.to_string();
+                        if let Some(channel) = relay.channels.lock().unwrap().get_mut(&name) {
+                            channel.last = Some(text.clone());
+                        }
+                        // No receivers just means nobody is watching
+                        let _ = tx.send(text);
+                    }
+                    Message::Close(_) => break,
+                    _ => {}
+                }
+            }
+        })
+}
+
+async fn watch(
+    State(relay): State<Relay>,
+    Path(name): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let subscription = relay
+        .channels
+        .lock()
+        .unwrap()
+        .get(&name)
+        .map(|channel| (channel.tx.subscribe(), channel.last.clone()));
+    let Some((rx, last)) = subscription else {
+        return (StatusCode::NOT_FOUND, "No such channel").into_response();
+    };
+    ws.on_upgrade(move |socket| async move {
+        viewer(socket, rx, last).await;
+        relay.cleanup(&name);
+    })
+}
+
+async fn viewer(socket: WebSocket, mut rx: broadcast::Receiver<String>, last: Option<String>) {
+    let (mut sink, mut source) = socket.split();
+    if let Some(last) = last {
+        if sink.send(Message::Text(last.into())).await.is_err() {
+            return;
+        }
+    }
+    loop {
+        tokio::select! {
+            message = rx.recv() => match message {
+                Ok(message) => {
+                    if sink.send(Message::Text(message.into())).await.is_err() {
+                        return;
+                    }
+                }
+                // A slow viewer only misses stale samples
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            },
+            incoming = source.next() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+async fn view(Path(name): Path<String>) -> Response {
+    if !valid_channel(&name) {
+        return (StatusCode::BAD_REQUEST, "Invalid channel name").into_response();
+    }
+    Html(VIEWER).into_response()
+}
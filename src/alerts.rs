@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use tracing::{info, warn};
+
+use crate::output::Sample;
+use crate::recovery::shell;
+
+/// Samples in a row past a threshold before an alert fires, so a single
+/// noisy reading does not.
+const CONFIRM_SAMPLES: u8 = 3;
+/// How far back inside a threshold the heart rate has to come before the
+/// alert can fire again.
+const HYSTERESIS_BPM: u16 = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    High,
+    Low,
+    ContactLost,
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertKind::High => f.write_str("high"),
+            AlertKind::Low => f.write_str("low"),
+            AlertKind::ContactLost => f.write_str("contact_lost"),
+        }
+    }
+}
+
+/// An alert that just fired.
+pub struct Alert {
+    pub kind: AlertKind,
+    pub bpm: u16,
+    pub message: String,
+}
+
+/// One condition with hysteresis: it trips after [`CONFIRM_SAMPLES`]
+/// matching samples and re-arms once the clear condition holds.
+#[derive(Default)]
+struct Trigger {
+    tripped: bool,
+    streak: u8,
+}
+
+impl Trigger {
+    /// Feed one sample; `true` when the alert should fire.
+    fn update(&mut self, past: bool, clear: bool) -> bool {
+        if self.tripped {
+            if clear {
+                *self = Trigger::default();
+            }
+            return false;
+        }
+        self.streak = if past { self.streak + 1 } else { 0 };
+        self.tripped = self.streak >= CONFIRM_SAMPLES;
+        self.tripped
+    }
+}
+
+/// Heart rate threshold and contact alerts, shown as desktop notifications
+/// and/or handed to commands.
+pub struct Alerts {
+    high: Option<u16>,
+    low: Option<u16>,
+    contact_lost: bool,
+    notify: bool,
+    commands: Vec<String>,
+    /// Per device, as each has its own wearer.
+    triggers: HashMap<(Option<String>, AlertKind), Trigger>,
+}
+
+impl Alerts {
+    /// `None` when no alert is configured.
+    pub fn new(
+        high: Option<u16>,
+        low: Option<u16>,
+        contact_lost: bool,
+        notify: bool,
+        commands: Vec<String>,
+    ) -> Option<Self> {
+        (high.is_some() || low.is_some() || contact_lost).then(|| Alerts {
+            high,
+            low,
+            contact_lost,
+            notify,
+            commands,
+            triggers: HashMap::new(),
+        })
+    }
+
+    /// Check `sample` against the thresholds and fire what tripped.
+    pub fn measurement(&mut self, sample: &Sample) -> Vec<Alert> {
+        let contact = sample.contact != Some(false);
+        let mut checks = Vec::new();
+        if let Some(high) = self.high {
+            checks.push((
+                AlertKind::High,
+                contact && sample.bpm >= high,
+                sample.bpm.saturating_add(HYSTERESIS_BPM) <= high,
+            ));
+        }
+        if let Some(low) = self.low {
+            checks.push((
+                AlertKind::Low,
+                contact && sample.bpm <= low,
+                sample.bpm >= low.saturating_add(HYSTERESIS_BPM),
+            ));
+        }
+        if self.contact_lost {
+            checks.push((AlertKind::ContactLost, !contact, contact));
+        }
+
+        let mut alerts = Vec::new();
+        for (kind, past, clear) in checks {
+            let trigger = self
+                .triggers
+                .entry((sample.device.clone(), kind))
+                .or_default();
+            if trigger.update(past, clear) {
+                alerts.push(Alert {
+                    kind,
+                    bpm: sample.bpm,
+                    message: message(kind, sample),
+                });
+            }
+        }
+        for alert in &alerts {
+            self.fire(alert, sample.device.as_deref());
+        }
+        alerts
+    }
+
+    fn fire(&self, alert: &Alert, device: Option<&str>) {
+        warn!("Alert: {}", alert.message);
+        if self.notify {
+            notify("Heart rate alert", &alert.message);
+        }
+        for command in &self.commands {
+            info!("Running alert command: {command}");
+            let child = shell(command)
+                .env("MIBAND_ALERT", alert.kind.to_string())
+                .env("MIBAND_ALERT_MESSAGE", &alert.message)
+                .env("MIBAND_BPM", alert.bpm.to_string())
+                .env("MIBAND_DEVICE", device.unwrap_or_default())
+                .spawn();
+            match child {
+                // Reaped in the background so a slow command holds up nothing
+                Ok(mut child) => {
+                    tokio::spawn(async move {
+                        match child.wait().await {
+                            Ok(status) if status.success() => {}
+                            Ok(status) => warn!("Alert command failed: {status}"),
+                            Err(err) => warn!("Cannot run alert command: {err}"),
+                        }
+                    });
+                }
+                Err(err) => warn!("Cannot run alert command: {err}"),
+            }
+        }
+    }
+}
+
+/// Show a desktop notification without holding up the caller.
+pub fn notify(summary: &'static str, body: &str) {
+    let body = body.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let shown = notify_rust::Notification::new()
+            .summary(summary)
+            .body(&body)
+            .show();
+        if let Err(err) = shown {
+            warn!("Cannot show notification: {err}");
+        }
+    });
+}
+
+fn message(kind: AlertKind, sample: &Sample) -> String {
+    let device = sample
+        .device
+        .as_deref()
+        .map_or(String::new(), |device| format!(" on {device}"));
+    match kind {
+        AlertKind::High => format!("Heart rate high: {} bpm{device}", sample.bpm),
+        AlertKind::Low => format!("Heart rate low: {} bpm{device}", sample.bpm),
+        AlertKind::ContactLost => format!("Skin contact lost{device}"),
+    }
+}
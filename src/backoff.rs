@@ -0,0 +1,50 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+/// Delay after the first failure; doubled after each further one.
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+
+/// Spaces out reconnection attempts exponentially, with jitter so several
+/// instances do not retry in lockstep.
+pub struct Backoff {
+    max_delay: Duration,
+    max_retries: Option<u32>,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(max_delay: Duration, max_retries: Option<u32>) -> Self {
+        Backoff {
+            max_delay,
+            max_retries,
+            failures: 0,
+        }
+    }
+
+    pub fn success(&mut self) {
+        self.failures = 0;
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Record a failed attempt. Returns how long to wait before the next one,
+    /// or `None` once the retries are used up.
+    pub fn failure(&mut self) -> Option<Duration> {
+        self.failures += 1;
+        if self
+            .max_retries
+            .is_some_and(|max_retries| self.failures > max_retries)
+        {
+            return None;
+        }
+        let delay = INITIAL_DELAY
+            .saturating_mul(1 << (self.failures - 1).min(16))
+            .min(self.max_delay);
+        // Anywhere between half and the full delay
+        let jitter = RandomState::new().hash_one(self.failures) % 1000;
+        Some(delay / 2 + delay / 2 * jitter as u32 / 1000)
+    }
+}
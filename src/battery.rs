@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Discharge needed before the rate is trusted, in percent.
+const MIN_DROP: u8 = 2;
+/// Time span needed before the rate is trusted.
+const MIN_SPAN: Duration = Duration::from_secs(60 * 60);
+
+/// Where the battery readings of all devices are kept, one JSON object per
+/// line.
+fn path() -> Option<PathBuf> {
+    Some(
+        dirs::data_local_dir()?
+            .join("miband-heart-rate")
+            .join("battery.jsonl"),
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+struct Reading {
+    /// Seconds since the Unix epoch.
+    ts: u64,
+    device: String,
+    level: u8,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+/// Add a reading of `device`'s battery to the history.
+pub fn record(device: &str, level: u8) -> Result<(), Box<dyn Error>> {
+    let path = path().ok_or("No data directory on this platform")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let reading = Reading {
+        ts: now(),
+        device: device.to_owned(),
+        level,
+    };
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(&reading)?)?;
+    Ok(())
+}
+
+/// How long `device`'s battery will probably last from now, going by how
+/// fast it drained since it was last charged.
+pub fn time_to_empty(device: &str) -> Option<Duration> {
+    let data = fs::read_to_string(path()?).ok()?;
+    let readings: Vec<(u64, u8)> = data
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Reading>(line).ok())
+        .filter(|reading| reading.device == device)
+        .map(|reading| (reading.ts, reading.level))
+        .collect();
+    estimate(&readings, now())
+}
+
+/// Extrapolate the discharge since the last charge, `readings` being
+/// `(seconds, percent)` in the order they were taken.
+fn estimate(readings: &[(u64, u8)], now: u64) -> Option<Duration> {
+    let &(end, level) = readings.last()?;
+    // Back to the last time the level went up
+    let start = readings
+        .windows(2)
+        .rposition(|pair| pair[1].1 > pair[0].1)
+        .map_or(0, |charged| charged + 1);
+    let (begin, full) = readings[start];
+    let drop = full.saturating_sub(level);
+    let span = end.saturating_sub(begin);
+    if drop < MIN_DROP || span < MIN_SPAN.as_secs() {
+        return None;
+    }
+    let left = span as f64 * f64::from(level) / f64::from(drop);
+    Some(Duration::from_secs_f64(left).saturating_sub(Duration::from_secs(now.saturating_sub(end))))
+}
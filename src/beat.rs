@@ -0,0 +1,106 @@
+use std::time::{Duration, SystemTime};
+
+use crate::hrv::PLAUSIBLE_RR_MS;
+
+/// Weight of the newest RR interval in the running estimate.
+const SMOOTHING: f64 = 0.3;
+
+/// Predicted timing of the next heartbeat.
+pub struct Beat {
+    pub next: SystemTime,
+    pub interval: Duration,
+}
+
+/// Predicts upcoming beats from the RR trend, so effects that pulse on each
+/// beat can be scheduled ahead of time instead of lagging behind the
+/// notification. One predictor per connection.
+#[derive(Default)]
+pub struct BeatPredictor {
+    /// Smoothed RR interval in seconds.
+    interval: Option<f64>,
+    /// Last RR interval seen, for the trend.
+    last_rr: Option<f64>,
+    /// Consecutive intervals rejected as outliers.
+    rejected: u32,
+}
+
+impl BeatPredictor {
+    /// `received` approximates the last beat: devices notify right after the
+    /// beat that closes the last RR interval. Needs RR intervals.
+    pub fn predict(&mut self, rr_intervals: &[Duration], received: SystemTime) -> Option<Beat> {
+        let mut trend = 0.0;
+        for rr in rr_intervals {
+            // A zero or absurd interval would stall the schedule below
+            if !PLAUSIBLE_RR_MS.contains(&(rr.as_secs_f64() * 1000.0)) {
+                continue;
+            }
+            let rr = rr.as_secs_f64();
+            // Ectopic beats and missed detections would throw the estimate off,
+            // but a run of them means the rate really changed
+            if let Some(interval) = self.interval {
+                if (rr - interval).abs() > interval * 0.3 && self.rejected < 3 {
+                    self.rejected += 1;
+                    continue;
+                }
+            }
+            if self.rejected >= 3 {
+                self.interval = None;
+                self.last_rr = None;
+            }
+            self.rejected = 0;
+            if let Some(last) = self.last_rr {
+                trend = rr - last;
+            }
+            self.interval = Some(match self.interval {
+                Some(interval) => interval + SMOOTHING * (rr - interval),
+                None => rr,
+            });
+            self.last_rr = Some(rr);
+        }
+        if rr_intervals.is_empty() {
+            return None;
+        }
+
+        // Continue half of the latest change, bounded to keep outliers sane
+        let interval = self.interval?;
+        let interval = interval + (trend / 2.0).clamp(-interval * 0.1, interval * 0.1);
+        let interval = Duration::from_secs_f64(interval);
+        if interval.is_zero() {
+            return None;
+        }
+        let mut next = received + interval;
+        // Notifications can arrive late; never predict a beat in the past
+        if let Ok(behind) = SystemTime::now().duration_since(next) {
+            let n = behind.as_nanos() / interval.as_nanos() + 1;
+            next += interval * u32::try_from(n).unwrap_or(u32::MAX);
+        }
+        Some(Beat { next, interval })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_zero_intervals() {
+        let mut predictor = BeatPredictor::default();
+        let now = SystemTime::now();
+        assert!(predictor.predict(&[Duration::ZERO], now).is_none());
+
+        let rr = Duration::from_millis(800);
+        let beat = predictor.predict(&[Duration::ZERO, rr], now).unwrap();
+        assert_eq!(beat.interval, rr);
+    }
+
+    #[test]
+    fn catches_up_with_late_notifications() {
+        let mut predictor = BeatPredictor::default();
+        let received = SystemTime::now() - Duration::from_secs(3600);
+        let beat = predictor
+            .predict(&[Duration::from_millis(1000)], received)
+            .unwrap();
+        assert!(beat.next >= SystemTime::now() - Duration::from_millis(10));
+        assert!(beat.next <= SystemTime::now() + Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+/// Correction applied to a device's raw BPM before analysis, e.g. derived from a
+/// comparison session against a chest strap.
+#[derive(Debug, Clone)]
+pub enum Calibration {
+    /// Added to every raw value.
+    Offset(i16),
+    /// `raw:corrected` points, linearly interpolated in between and extended
+    /// with the nearest point's offset outside.
+    Map(Vec<(u16, u16)>),
+}
+
+impl Calibration {
+    pub fn apply(&self, raw: u16) -> u16 {
+        match self {
+            Calibration::Offset(offset) => raw.saturating_add_signed(*offset),
+            Calibration::Map(points) => {
+                let offset = |(r, c): (u16, u16)| c as i32 - r as i32;
+                let corrected = match points.iter().position(|&(r, _)| r >= raw) {
+                    Some(0) => raw as i32 + offset(points[0]),
+                    Some(i) => {
+                        let (r0, c0) = points[i - 1];
+                        let (r1, c1) = points[i];
+                        c0 as i32 + (raw - r0) as i32 * (c1 as i32 - c0 as i32) / (r1 - r0) as i32
+                    }
+                    None => raw as i32 + offset(points[points.len() - 1]),
+                };
+                corrected.clamp(0, u16::MAX as i32) as u16
+            }
+        }
+    }
+}
+
+impl FromStr for Calibration {
+    type Err = String;
+
+    /// `+3` / `-2` for an offset, `60:58,120:124,180:176` for a mapping.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains(':') {
+            return s
+                .parse()
+                .map(Calibration::Offset)
+                .map_err(|err| format!("invalid offset {s:?}: {err}"));
+        }
+
+        let mut points = s
+            .split(',')
+            .map(|point| {
+                let (raw, corrected) = point
+                    .split_once(':')
+                    .ok_or_else(|| format!("expected raw:corrected, got {point:?}"))?;
+                let parse = |v: &str| {
+                    v.trim()
+                        .parse::<u16>()
+                        .map_err(|err| format!("invalid BPM {v:?}: {err}"))
+                };
+                Ok((parse(raw)?, parse(corrected)?))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        points.sort_by_key(|&(raw, _)| raw);
+        points.dedup_by_key(|&mut (raw, _)| raw);
+        Ok(Calibration::Map(points))
+    }
+}
+
+/// A calibration for the devices whose address or name is `device`, or for
+/// every device without one of its own.
+#[derive(Debug, Clone)]
+pub struct DeviceCalibration {
+    pub device: Option<String>,
+    pub calibration: Calibration,
+}
+
+impl FromStr for DeviceCalibration {
+    type Err = String;
+
+    /// `AA:BB:CC:DD:EE:FF=-3` or `Mi Smart Band 7=60:58,120:124` for one
+    /// device, a bare calibration for every device.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (device, spec) = match s.split_once('=') {
+            Some((device, spec)) if !device.trim().is_empty() => {
+                (Some(device.trim().to_owned()), spec)
+            }
+            Some(_) => return Err(format!("missing device before `=` in {s:?}")),
+            None => (None, s),
+        };
+        Ok(DeviceCalibration {
+            device,
+            calibration: spec.parse()?,
+        })
+    }
+}
+
+/// The calibration for the device with platform identifier `id` and
+/// advertised `name`: its own, matched like `--address` or `--device`, before
+/// the one for every device.
+pub fn for_device<'a>(
+    calibrations: &'a [DeviceCalibration],
+    id: &str,
+    name: Option<&str>,
+) -> Option<&'a Calibration> {
+    let id = id.to_lowercase();
+    let matches = |device: &str| {
+        id.contains(&device.to_lowercase())
+            || name.is_some_and(|name| name.eq_ignore_ascii_case(device))
+    };
+    calibrations
+        .iter()
+        .find(|c| c.device.as_deref().is_some_and(matches))
+        .or_else(|| calibrations.iter().find(|c| c.device.is_none()))
+        .map(|c| &c.calibration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset() {
+        let calibration: Calibration = "+3".parse().unwrap();
+        assert_eq!(calibration.apply(60), 63);
+        let calibration: Calibration = "-5".parse().unwrap();
+        assert_eq!(calibration.apply(60), 55);
+        assert_eq!(calibration.apply(2), 0);
+    }
+
+    #[test]
+    fn map_interpolates_between_points() {
+        let calibration: Calibration = "120:124,60:58".parse().unwrap();
+        assert_eq!(calibration.apply(60), 58);
+        assert_eq!(calibration.apply(90), 91);
+        assert_eq!(calibration.apply(120), 124);
+    }
+
+    #[test]
+    fn map_extends_the_nearest_offset() {
+        let calibration: Calibration = "60:58,120:124".parse().unwrap();
+        assert_eq!(calibration.apply(50), 48);
+        assert_eq!(calibration.apply(150), 154);
+        assert_eq!(calibration.apply(1), 0);
+        assert_eq!(calibration.apply(u16::MAX), u16::MAX);
+    }
+
+    #[test]
+    fn map_keeps_the_first_of_duplicate_points() {
+        let calibration: Calibration = "60:58,60:70, 120 : 124".parse().unwrap();
+        let Calibration::Map(points) = calibration else {
+            panic!("expected a map");
+        };
+        assert_eq!(points, [(60, 58), (120, 124)]);
+    }
+
+    #[test]
+    fn per_device() {
+        let calibrations: Vec<DeviceCalibration> =
+            ["-1", "AA:BB:CC:DD:EE:FF=+3", "Mi Smart Band 7=60:50"]
+                .iter()
+                .map(|s| s.parse().unwrap())
+                .collect();
+        let apply = |id: &str, name: Option<&str>| {
+            for_device(&calibrations, id, name).map(|calibration| calibration.apply(60))
+        };
+        assert_eq!(apply("hci0/dev_AA_BB", None), Some(59));
+        assert_eq!(apply("aa:bb:cc:dd:ee:ff", None), Some(63));
+        assert_eq!(
+            apply("11:22:33:44:55:66", Some("mi smart band 7")),
+            Some(50)
+        );
+        // Without one for every device, others stay as they are
+        assert!(for_device(&calibrations[1..], "11:22:33:44:55:66", None).is_none());
+
+        assert!("=+3".parse::<DeviceCalibration>().is_err());
+        assert!("AA:BB=".parse::<DeviceCalibration>().is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!("abc".parse::<Calibration>().is_err());
+        assert!("60:58,120".parse::<Calibration>().is_err());
+        assert!("60:-1".parse::<Calibration>().is_err());
+    }
+}
@@ -0,0 +1,480 @@
+use std::error::Error;
+use std::future::pending;
+use std::time::Duration;
+
+use bluest::{
+    btuuid::bluetooth_uuid_from_u16, Adapter, Characteristic, Descriptor, Device, DeviceId,
+    Service, Uuid,
+};
+use futures_lite::stream::{Stream, StreamExt};
+use tokio::time::{interval, sleep_until, Instant};
+
+use crate::hrm::{FlagDrift, HeartRateMeasurement, ParseError, Quirks, SensorLocation};
+use crate::timeout::{timeout, Operation, Timeouts};
+use crate::{HRM_UUID, HRS_UUID};
+
+/// Client Characteristic Configuration descriptor.
+const CCCD_UUID: Uuid = bluetooth_uuid_from_u16(0x2902);
+/// CCCD value with the notification bit set.
+const CCCD_NOTIFY: [u8; 2] = [0x01, 0x00];
+/// CCCD value with notifications and indications off.
+const CCCD_OFF: [u8; 2] = [0x00, 0x00];
+
+/// Battery Service and its Battery Level characteristic.
+const BATTERY_SERVICE_UUID: Uuid = bluetooth_uuid_from_u16(0x180F);
+const BATTERY_LEVEL_UUID: Uuid = bluetooth_uuid_from_u16(0x2A19);
+/// Optional Body Sensor Location characteristic of the Heart Rate Service.
+const BODY_SENSOR_LOCATION_UUID: Uuid = bluetooth_uuid_from_u16(0x2A38);
+/// Immediate Alert Service and its Alert Level characteristic.
+const IMMEDIATE_ALERT_SERVICE_UUID: Uuid = bluetooth_uuid_from_u16(0x1802);
+const ALERT_LEVEL_UUID: Uuid = bluetooth_uuid_from_u16(0x2A06);
+/// Alert Level value that makes the device vibrate or beep.
+const HIGH_ALERT: [u8; 1] = [0x02];
+
+/// How long to keep collecting candidates after the first one shows up.
+const SCAN_WINDOW: Duration = Duration::from_secs(3);
+/// How often to re-check for devices connected by the OS during a scan.
+const CONNECTED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Restricts which devices [`HeartRateClient::scan`] picks. Empty matches
+/// everything.
+#[derive(Debug, Default, Clone)]
+pub struct DeviceFilter {
+    /// Advertised name, compared case-insensitively.
+    pub name: Option<String>,
+    /// Bluetooth address or platform identifier. Matches if the identifier
+    /// contains it, so a MAC address also matches Windows' longer IDs.
+    pub address: Option<String>,
+}
+
+impl DeviceFilter {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.address.is_none()
+    }
+
+    pub async fn matches(&self, device: &Device) -> bool {
+        if let Some(address) = &self.address {
+            let id = device.id().to_string().to_lowercase();
+            if !id.contains(&address.to_lowercase()) {
+                return false;
+            }
+        }
+        if let Some(name) = &self.name {
+            match device.name_async().await {
+                Ok(actual) if actual.eq_ignore_ascii_case(name) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Finds heart rate devices and connects to them.
+pub struct HeartRateClient {
+    adapter: Adapter,
+    timeouts: Timeouts,
+    quirks: Quirks,
+    filter: DeviceFilter,
+}
+
+impl HeartRateClient {
+    pub fn new(adapter: Adapter) -> Self {
+        HeartRateClient {
+            adapter,
+            timeouts: Timeouts::default(),
+            quirks: Quirks::default(),
+            filter: DeviceFilter::default(),
+        }
+    }
+
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: DeviceFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn filter(&self) -> &DeviceFilter {
+        &self.filter
+    }
+
+    pub fn adapter(&self) -> &Adapter {
+        &self.adapter
+    }
+
+    /// Open a device by its platform identifier without scanning.
+    pub async fn open(&self, id: &DeviceId) -> Result<Device, Box<dyn Error>> {
+        Ok(self.adapter.open_device(id).await?)
+    }
+
+    /// Scan for heart rate devices matching the filter while polling the ones
+    /// already connected by the OS, and pick the best candidate: already
+    /// connected > `preferred` > paired > strongest RSSI. An already-connected
+    /// device is taken as soon as it is seen; otherwise candidates are
+    /// collected for a few seconds after the first sighting.
+    pub async fn scan(&self, preferred: Option<&DeviceId>) -> Result<Device, Box<dyn Error>> {
+        self.candidates(preferred, false)
+            .await?
+            .into_iter()
+            .max_by_key(Candidate::priority)
+            .map(|c| c.device)
+            .ok_or_else(|| "Scan ended without finding a heart rate device".into())
+    }
+
+    /// Scan for a few seconds and return every heart rate device matching the
+    /// filter, best candidates first. Empty if none showed up.
+    pub async fn scan_all(&self) -> Result<Vec<Device>, Box<dyn Error>> {
+        let mut candidates = self.candidates(None, true).await?;
+        candidates.sort_by_key(|c| std::cmp::Reverse(c.priority()));
+        Ok(candidates.into_iter().map(|c| c.device).collect())
+    }
+
+    /// Scan for a few seconds and report every heart rate device matching the
+    /// filter with what is known about it, best candidates first.
+    pub async fn scan_nearby(&self) -> Result<Vec<Nearby>, Box<dyn Error>> {
+        let mut candidates = self.candidates(None, true).await?;
+        candidates.sort_by_key(|c| std::cmp::Reverse(c.priority()));
+        Ok(candidates
+            .into_iter()
+            .map(|c| Nearby {
+                device: c.device,
+                rssi: c.rssi,
+                connected: c.connected,
+                paired: c.paired,
+            })
+            .collect())
+    }
+
+    /// Collect candidates until [`SCAN_WINDOW`] after the first sighting, or
+    /// with `all` for [`SCAN_WINDOW`] from the start, without stopping early
+    /// for an already-connected device.
+    async fn candidates(
+        &self,
+        preferred: Option<&DeviceId>,
+        all: bool,
+    ) -> Result<Vec<Candidate>, Box<dyn Error>> {
+        let mut scan = self.adapter.scan(&[HRS_UUID]).await?;
+
+        let mut candidates: Vec<Candidate> = Vec::new();
+        let mut poll = interval(CONNECTED_POLL_INTERVAL);
+        let mut deadline = all.then(|| Instant::now() + SCAN_WINDOW);
+        loop {
+            let window = async move {
+                match deadline {
+                    Some(deadline) => sleep_until(deadline).await,
+                    None => pending().await,
+                }
+            };
+
+            let found = tokio::select! {
+                _ = poll.tick() => {
+                    let mut found = Vec::new();
+                    for device in self.adapter.connected_devices_with_services(&[HRS_UUID]).await? {
+                        if self.filter.matches(&device).await {
+                            found.push(Candidate::new(device, true, None, preferred).await);
+                        }
+                    }
+                    found
+                }
+                Some(advertising) = scan.next() => {
+                    let mut found = Vec::new();
+                    if self.filter.matches(&advertising.device).await {
+                        found.push(
+                            Candidate::new(advertising.device, false, advertising.rssi, preferred).await,
+                        );
+                    }
+                    found
+                }
+                _ = window => break,
+            };
+
+            for candidate in found {
+                match candidates
+                    .iter_mut()
+                    .find(|c| c.device.id() == candidate.device.id())
+                {
+                    Some(existing) => existing.merge(candidate),
+                    None => candidates.push(candidate),
+                }
+            }
+
+            if !all && candidates.iter().any(|c| c.connected) {
+                break;
+            }
+            if deadline.is_none() && !candidates.is_empty() {
+                deadline = Some(Instant::now() + SCAN_WINDOW);
+            }
+        }
+        Ok(candidates)
+    }
+
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    /// Connect to `device` and find its Heart Rate Measurement characteristic.
+    pub async fn connect(&self, device: &Device) -> Result<Connection, Box<dyn Error>> {
+        let timeouts = &self.timeouts;
+
+        // Connect
+        if !device.is_connected().await {
+            timeout(
+                Operation::Connect,
+                timeouts.connect,
+                self.adapter.connect_device(device),
+            )
+            .await?;
+        }
+
+        // Discover services
+        let heart_rate_services = timeout(
+            Operation::DiscoverServices,
+            timeouts.discover,
+            device.discover_services_with_uuid(HRS_UUID),
+        )
+        .await?;
+        let heart_rate_service = heart_rate_services
+            .first()
+            .ok_or("Device should has one heart rate service at least")?;
+
+        // Discover
+        let heart_rate_measurements = timeout(
+            Operation::DiscoverCharacteristics,
+            timeouts.discover,
+            heart_rate_service.discover_characteristics_with_uuid(HRM_UUID),
+        )
+        .await?;
+        let heart_rate_measurement = heart_rate_measurements.first().ok_or(
+            "HeartRateService should has one heart rate measurement characteristic at least",
+        )?;
+
+        // Optional, so a device without it or a failed read is no error
+        let sensor_location = read_sensor_location(heart_rate_service, timeouts)
+            .await
+            .ok()
+            .flatten();
+
+        Ok(Connection {
+            device: device.clone(),
+            characteristic: heart_rate_measurement.clone(),
+            sensor_location,
+            timeouts: *timeouts,
+            quirks: self.quirks,
+        })
+    }
+}
+
+async fn read_sensor_location(
+    service: &Service,
+    timeouts: &Timeouts,
+) -> Result<Option<SensorLocation>, Box<dyn Error>> {
+    let characteristics = timeout(
+        Operation::DiscoverCharacteristics,
+        timeouts.discover,
+        service.discover_characteristics_with_uuid(BODY_SENSOR_LOCATION_UUID),
+    )
+    .await?;
+    let Some(characteristic) = characteristics.first() else {
+        return Ok(None);
+    };
+    let value = timeout(Operation::Read, timeouts.gatt, characteristic.read()).await?;
+    Ok(SensorLocation::parse(&value))
+}
+
+/// A connected heart rate device.
+pub struct Connection {
+    device: Device,
+    characteristic: Characteristic,
+    sensor_location: Option<SensorLocation>,
+    timeouts: Timeouts,
+    quirks: Quirks,
+}
+
+impl Connection {
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Where the sensor is worn, if the device tells.
+    pub fn sensor_location(&self) -> Option<SensorLocation> {
+        self.sensor_location
+    }
+
+    /// Largest notification payload the link allows (ATT MTU - 3), where the
+    /// backend exposes it.
+    pub fn max_payload(&self) -> Result<usize, Box<dyn Error>> {
+        Ok(self.characteristic.max_write_len()?)
+    }
+
+    /// Read the Battery Level (0x2A19) in percent, for devices with a Battery
+    /// Service.
+    pub async fn battery_level(&self) -> Result<u8, Box<dyn Error>> {
+        let services = timeout(
+            Operation::DiscoverServices,
+            self.timeouts.discover,
+            self.device.discover_services_with_uuid(BATTERY_SERVICE_UUID),
+        )
+        .await?;
+        let service = services.first().ok_or("Device has no battery service")?;
+        let levels = timeout(
+            Operation::DiscoverCharacteristics,
+            self.timeouts.discover,
+            service.discover_characteristics_with_uuid(BATTERY_LEVEL_UUID),
+        )
+        .await?;
+        let level = levels.first().ok_or("Device has no battery level")?;
+        let value = timeout(Operation::Read, self.timeouts.gatt, level.read()).await?;
+        Ok(*value.first().ok_or("Empty battery level")?)
+    }
+
+    /// Make the device vibrate (or beep) through the Immediate Alert Service,
+    /// for devices that have one.
+    pub async fn vibrate(&self) -> Result<(), Box<dyn Error>> {
+        let services = timeout(
+            Operation::DiscoverServices,
+            self.timeouts.discover,
+            self.device.discover_services_with_uuid(IMMEDIATE_ALERT_SERVICE_UUID),
+        )
+        .await?;
+        let service = services.first().ok_or("Device has no immediate alert service")?;
+        let levels = timeout(
+            Operation::DiscoverCharacteristics,
+            self.timeouts.discover,
+            service.discover_characteristics_with_uuid(ALERT_LEVEL_UUID),
+        )
+        .await?;
+        let level = levels.first().ok_or("Device has no alert level")?;
+        let alert = level.write_without_response(&HIGH_ALERT);
+        timeout(Operation::Write, self.timeouts.gatt, alert).await
+    }
+
+    /// Read back the CCCD and re-enable notifications if the device reset it
+    /// (Mi Bands do when their screen wakes). Returns whether it had to be
+    /// repaired. Backends that manage the CCCD themselves may refuse access.
+    pub async fn repair_notifications(&self) -> Result<bool, Box<dyn Error>> {
+        let cccd = self.cccd().await?;
+        let value = timeout(Operation::Read, self.timeouts.gatt, cccd.read()).await?;
+        if value
+            .first()
+            .is_some_and(|flags| flags & CCCD_NOTIFY[0] != 0)
+        {
+            return Ok(false);
+        }
+        timeout(Operation::Write, self.timeouts.gatt, cccd.write(&CCCD_NOTIFY)).await?;
+        Ok(true)
+    }
+
+    /// Turn notifications off before letting go of the device, so it does not
+    /// keep sending until the link times out. Drop the
+    /// [`measurements`](Self::measurements) stream first; backends that
+    /// manage the CCCD themselves may refuse, having done it on drop.
+    pub async fn unsubscribe(&self) -> Result<(), Box<dyn Error>> {
+        let cccd = self.cccd().await?;
+        timeout(Operation::Write, self.timeouts.gatt, cccd.write(&CCCD_OFF)).await
+    }
+
+    async fn cccd(&self) -> Result<Descriptor, Box<dyn Error>> {
+        let descriptors = timeout(
+            Operation::DiscoverDescriptors,
+            self.timeouts.discover,
+            self.characteristic.discover_descriptors(),
+        )
+        .await?;
+        let cccd = descriptors
+            .into_iter()
+            .find(|descriptor| descriptor.uuid() == CCCD_UUID)
+            .ok_or("Heart rate measurement has no CCCD")?;
+        Ok(cccd)
+    }
+
+    /// Subscribe to measurements. The stream ends when the device disconnects
+    /// or notifications fail; malformed packets come through as errors without
+    /// ending it.
+    pub async fn measurements(
+        &self,
+    ) -> Result<impl Stream<Item = Result<HeartRateMeasurement, ParseError>> + '_, Box<dyn Error>>
+    {
+        let updates = timeout(
+            Operation::Subscribe,
+            self.timeouts.subscribe,
+            self.characteristic.notify(),
+        )
+        .await?;
+
+        // A full packet may have lost RR intervals at the end
+        let max_payload = self.max_payload().ok();
+        let quirks = self.quirks;
+        let mut drift = FlagDrift::default();
+        Ok(updates
+            .take_while(Result::is_ok)
+            .filter_map(Result::ok)
+            .map(move |data| {
+                let mut measurement = drift.parse(&data, &quirks)?;
+                measurement.possibly_truncated = measurement.has_rr_intervals
+                    && max_payload.is_some_and(|max_payload| data.len() >= max_payload);
+                Ok(measurement)
+            }))
+    }
+}
+
+/// A heart rate device found by [`HeartRateClient::scan_nearby`].
+pub struct Nearby {
+    pub device: Device,
+    /// Signal strength in dBm, if it was advertising.
+    pub rssi: Option<i16>,
+    /// Already connected by the OS.
+    pub connected: bool,
+    pub paired: bool,
+}
+
+/// A heart rate device seen while looking for one to connect.
+struct Candidate {
+    device: Device,
+    connected: bool,
+    preferred: bool,
+    paired: bool,
+    rssi: Option<i16>,
+}
+
+impl Candidate {
+    async fn new(
+        device: Device,
+        connected: bool,
+        rssi: Option<i16>,
+        preferred: Option<&DeviceId>,
+    ) -> Self {
+        let paired = device.is_paired().await.unwrap_or(false);
+        let preferred = preferred == Some(&device.id());
+        Candidate {
+            device,
+            connected,
+            preferred,
+            paired,
+            rssi,
+        }
+    }
+
+    /// Already-connected > preferred > paired > strongest RSSI.
+    fn priority(&self) -> (bool, bool, bool, i16) {
+        (
+            self.connected,
+            self.preferred,
+            self.paired,
+            self.rssi.unwrap_or(i16::MIN),
+        )
+    }
+
+    fn merge(&mut self, other: Candidate) {
+        self.connected |= other.connected;
+        self.paired |= other.paired;
+        self.rssi = other.rssi.or(self.rssi);
+    }
+}
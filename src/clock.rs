@@ -0,0 +1,178 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tracing::warn;
+
+/// Disagreement between the wall clock and the session timeline above which
+/// the wall clock is taken to have jumped.
+const MAX_SKEW: Duration = Duration::from_secs(2);
+
+/// A jump of the system clock during the session.
+#[derive(Clone)]
+pub struct ClockCorrection {
+    /// Wall clock time after the jump, in ms since the Unix epoch.
+    pub ts: i64,
+    /// How far the wall clock jumped, negative for backwards.
+    pub skew_ms: i64,
+    /// Whether the timeline followed the jump. Backward jumps are held so
+    /// timestamps never go back.
+    pub applied: bool,
+}
+
+/// Timestamps for exported samples that stay monotonic when the system clock
+/// is stepped (NTP) or the machine resumes from suspend. Time advances with
+/// the monotonic clock; forward jumps of the wall clock are followed, as
+/// after a suspend, backward ones are held.
+pub struct SessionClock {
+    /// Timeline time (ms since the Unix epoch) at `anchor`.
+    anchor_ms: i64,
+    anchor: Instant,
+    /// Wall clock minus timeline after a held backward jump.
+    held_ms: i64,
+    corrections: Vec<ClockCorrection>,
+}
+
+impl Default for SessionClock {
+    fn default() -> Self {
+        SessionClock {
+            anchor_ms: unix_ms(SystemTime::now()),
+            anchor: Instant::now(),
+            held_ms: 0,
+            corrections: Vec::new(),
+        }
+    }
+}
+
+fn unix_ms(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(err) => -(err.duration().as_millis() as i64),
+    }
+}
+
+impl SessionClock {
+    /// Timeline time of something that happened at `at`.
+    pub fn stamp(&mut self, at: Instant) -> SystemTime {
+        let timeline =
+            self.anchor_ms + at.saturating_duration_since(self.anchor).as_millis() as i64;
+        let wall = unix_ms(SystemTime::now()) - at.elapsed().as_millis() as i64;
+        let skew = wall - timeline - self.held_ms;
+        let time = if skew.abs() > MAX_SKEW.as_millis() as i64 {
+            let applied = skew > 0 && self.held_ms + skew > 0;
+            warn!(
+                "System clock jumped by {:+.1}s, {}",
+                skew as f64 / 1000.0,
+                if applied {
+                    "following it in exports"
+                } else {
+                    "keeping exported timestamps monotonic"
+                }
+            );
+            self.corrections.push(ClockCorrection {
+                ts: wall,
+                skew_ms: skew,
+                applied,
+            });
+            if applied {
+                self.anchor_ms = wall;
+                self.anchor = at;
+                self.held_ms = 0;
+                wall
+            } else {
+                self.held_ms += skew;
+                timeline
+            }
+        } else {
+            timeline
+        };
+        UNIX_EPOCH + Duration::from_millis(time.max(0) as u64)
+    }
+
+    /// Continue from the wall clock after a system suspend, which the
+    /// monotonic clock did not see, without counting it as a jump.
+    pub fn resumed(&mut self) {
+        let now = Instant::now();
+        let timeline =
+            self.anchor_ms + now.saturating_duration_since(self.anchor).as_millis() as i64;
+        let wall = unix_ms(SystemTime::now());
+        self.anchor_ms = wall.max(timeline);
+        self.anchor = now;
+        // Still holding a backward jump if the wall clock is behind
+        self.held_ms = wall - self.anchor_ms;
+    }
+
+    pub fn corrections(&self) -> &[ClockCorrection] {
+        &self.corrections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A clock whose timeline is `offset_ms` behind the wall clock, as if the
+    /// wall clock had just jumped by that much.
+    fn jumped(offset_ms: i64) -> SessionClock {
+        SessionClock {
+            anchor_ms: unix_ms(SystemTime::now()) - offset_ms,
+            ..SessionClock::default()
+        }
+    }
+
+    #[test]
+    fn follows_the_wall_clock_without_jumps() {
+        let mut clock = SessionClock::default();
+        let stamped = unix_ms(clock.stamp(Instant::now()));
+        assert!((stamped - unix_ms(SystemTime::now())).abs() < 1000);
+        assert!(clock.corrections().is_empty());
+    }
+
+    #[test]
+    fn follows_a_forward_jump() {
+        let mut clock = jumped(60_000);
+        let stamped = unix_ms(clock.stamp(Instant::now()));
+        assert!((stamped - unix_ms(SystemTime::now())).abs() < 1000);
+
+        let [correction] = clock.corrections() else {
+            panic!("expected one correction");
+        };
+        assert!(correction.applied);
+        assert!((correction.skew_ms - 60_000).abs() < 1000);
+
+        // The timeline continues from the jump without another correction
+        clock.stamp(Instant::now());
+        assert_eq!(clock.corrections().len(), 1);
+    }
+
+    #[test]
+    fn holds_a_backward_jump() {
+        let mut clock = jumped(-60_000);
+        let first = clock.stamp(Instant::now());
+        assert!(unix_ms(first) - unix_ms(SystemTime::now()) > 59_000);
+
+        let [correction] = clock.corrections() else {
+            panic!("expected one correction");
+        };
+        assert!(!correction.applied);
+        assert!((correction.skew_ms + 60_000).abs() < 1000);
+
+        // Still monotonic, and the held skew is not reported again
+        let second = clock.stamp(Instant::now());
+        assert!(second >= first);
+        assert_eq!(clock.corrections().len(), 1);
+    }
+
+    #[test]
+    fn resumes_from_the_wall_clock() {
+        let mut clock = jumped(60_000);
+        clock.resumed();
+        let stamped = unix_ms(clock.stamp(Instant::now()));
+        assert!((stamped - unix_ms(SystemTime::now())).abs() < 1000);
+        assert!(clock.corrections().is_empty());
+
+        // but never back in time, nor reporting a held jump again
+        let mut clock = jumped(-60_000);
+        clock.resumed();
+        assert!(unix_ms(clock.stamp(Instant::now())) - unix_ms(SystemTime::now()) > 59_000);
+        assert!(clock.corrections().is_empty());
+    }
+}
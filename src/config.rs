@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command};
+use serde::Deserialize;
+
+/// `~/.config/miband-heart-rate/config.toml` on Linux, the platform's
+/// configuration directory elsewhere.
+pub fn path() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join("miband-heart-rate")
+            .join("config.toml"),
+    )
+}
+
+/// Named sets of options, e.g.
+///
+/// ```toml
+/// [profiles.workout]
+/// address = "AA:BB:CC:DD:EE:FF"
+/// max-hr = 185
+/// export = ["~/hr/{session_id}.fit"]
+/// ```
+#[derive(Deserialize)]
+struct Config {
+    #[serde(default)]
+    profiles: BTreeMap<String, toml::Table>,
+}
+
+/// Arguments for the options of profile `name` that `matches` did not get on
+/// the command line, to append to it. Keys are the long option names of
+/// `command`, flags take booleans and repeatable options arrays.
+pub fn profile_args(
+    name: &str,
+    command: &Command,
+    matches: &ArgMatches,
+) -> Result<Vec<OsString>, Box<dyn Error>> {
+    let path = path().ok_or("Cannot find the configuration directory")?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|err| format!("Cannot read {}: {err}", path.display()))?;
+    let config: Config =
+        toml::from_str(&text).map_err(|err| format!("Invalid {}: {err}", path.display()))?;
+    let profile = config
+        .profiles
+        .get(name)
+        .ok_or_else(|| format!("No profile `{name}` in {}", path.display()))?;
+
+    let mut args = Vec::new();
+    for (key, value) in profile {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .filter(|arg| !matches!(arg.get_id().as_str(), "profile" | "help" | "version"))
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .ok_or_else(|| format!("Unknown option `{key}` in profile `{name}`"))?;
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let values = match value {
+            toml::Value::Array(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => args.push(format!("--{long}")),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(value) => args.push(format!("--{long}={value}")),
+                toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Datetime(_) => {
+                    args.push(format!("--{long}={value}"))
+                }
+                // `KEY=VALUE` pairs, e.g. calibrations by device
+                toml::Value::Table(table) => {
+                    for (pair_key, value) in table {
+                        let value = match value {
+                            toml::Value::String(value) => value.clone(),
+                            toml::Value::Integer(_) | toml::Value::Float(_) => value.to_string(),
+                            _ => {
+                                return Err(format!(
+                                    "Unsupported value for `{key}.{pair_key}` in profile `{name}`"
+                                )
+                                .into())
+                            }
+                        };
+                        args.push(format!("--{long}={pair_key}={value}"));
+                    }
+                }
+                toml::Value::Array(_) => {
+                    return Err(format!("Unsupported value for `{key}` in profile `{name}`").into())
+                }
+            }
+        }
+    }
+    Ok(args.into_iter().map(OsString::from).collect())
+}
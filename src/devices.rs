@@ -0,0 +1,103 @@
+use std::error::Error;
+
+use bluest::Device;
+use miband_heart_rate::{HeartRateClient, HRS_UUID};
+use tracing::info;
+
+use crate::remember;
+
+/// `name (id)`, or just the ID for devices without a name.
+async fn describe(device: &Device) -> String {
+    match device.name_async().await {
+        Ok(name) => format!("{name} ({})", device.id()),
+        Err(_) => device.id().to_string(),
+    }
+}
+
+/// List nearby heart rate devices, best candidates first.
+pub async fn scan(client: &HeartRateClient) -> Result<(), Box<dyn Error>> {
+    info!("Scanning for heart rate devices...");
+    let nearby = client.scan_nearby().await?;
+    if nearby.is_empty() {
+        println!("No heart rate devices found");
+    }
+    for found in nearby {
+        let mut line = describe(&found.device).await;
+        match found.rssi {
+            Some(rssi) => line += &format!("  RSSI {rssi} dBm"),
+            None => line += "  RSSI n/a",
+        }
+        if found.connected {
+            line += "  connected";
+        }
+        if found.paired {
+            line += "  paired";
+        }
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Pair with the device whose name or address matches `query` and make it
+/// the remembered device.
+pub async fn pair(client: &HeartRateClient, query: &str) -> Result<(), Box<dyn Error>> {
+    info!("Looking for {query}...");
+    let query = query.to_lowercase();
+    let mut target = None;
+    for found in client.scan_nearby().await? {
+        let name = found.device.name_async().await.unwrap_or_default();
+        let id = found.device.id().to_string().to_lowercase();
+        if name.to_lowercase() == query || id.contains(&query) {
+            target = Some(found);
+            break;
+        }
+    }
+    let found = target.ok_or("No matching heart rate device found; is it advertising?")?;
+    let description = describe(&found.device).await;
+    if found.paired {
+        println!("{description} is already paired");
+    } else {
+        found.device.pair().await?;
+        println!("Paired with {description}");
+    }
+    remember::save(&found.device.id())?;
+    println!("It is now the remembered device");
+    Ok(())
+}
+
+/// List the remembered device and heart rate devices the OS is connected to.
+pub async fn list(client: &HeartRateClient) -> Result<(), Box<dyn Error>> {
+    match remember::load() {
+        Some(id) => match client.open(&id).await {
+            Ok(device) => {
+                let paired = device.is_paired().await.unwrap_or(false);
+                let paired = if paired { ", paired" } else { "" };
+                println!("Remembered: {}{paired}", describe(&device).await);
+            }
+            Err(err) => println!("Remembered: {id:?} (not available: {err})"),
+        },
+        None => println!("Remembered: none"),
+    }
+    let connected = client
+        .adapter()
+        .connected_devices_with_services(&[HRS_UUID])
+        .await?;
+    if connected.is_empty() {
+        println!("Connected: none");
+    }
+    for device in connected {
+        let mut line = describe(&device).await;
+        // Already connected, so this only discovers and reads
+        match client
+            .connect(&device)
+            .await
+            .map(|connection| connection.sensor_location())
+        {
+            Ok(Some(location)) => line += &format!(", sensor location: {location}"),
+            Ok(None) => {}
+            Err(err) => line += &format!(" (not available: {err})"),
+        }
+        println!("Connected: {line}");
+    }
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use bluest::error::ErrorKind;
+use bluest::Adapter;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+#[cfg(target_os = "linux")]
+mod linux;
+
+/// How long a sink gets to accept a connection.
+const SINK_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        })
+    }
+}
+
+/// Outcome of a single environment check, with a hint on how to fix it.
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    pub fix: Option<&'static str>,
+}
+
+impl Check {
+    pub fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    pub fn warn(name: &'static str, detail: impl Into<String>, fix: &'static str) -> Self {
+        Check {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            fix: Some(fix),
+        }
+    }
+
+    pub fn fail(name: &'static str, detail: impl Into<String>, fix: &'static str) -> Self {
+        Check {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            fix: Some(fix),
+        }
+    }
+}
+
+/// An output on another machine that the streaming options point at.
+pub struct Sink {
+    pub name: &'static str,
+    pub url: String,
+    /// What to do when it cannot be reached.
+    pub fix: &'static str,
+}
+
+/// Run every check, print the results and fail if any check failed.
+pub async fn run(sinks: &[Sink]) -> Result<(), Box<dyn Error>> {
+    let mut checks = vec![build()];
+    checks.extend(platform());
+    checks.extend(adapter().await);
+    for sink in sinks {
+        checks.push(reachable(sink).await);
+    }
+
+    for check in &checks {
+        println!("[{:>4}] {}: {}", check.status, check.name, check.detail);
+        if let Some(fix) = check.fix {
+            println!("       fix: {fix}");
+        }
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    if failed > 0 {
+        return Err(format!("{failed} check(s) failed").into());
+    }
+    println!("All checks passed");
+    Ok(())
+}
+
+/// Turn a generic adapter problem into an error naming the most likely cause.
+pub fn adapter_error(problem: &str) -> Box<dyn Error> {
+    match platform_adapter()
+        .into_iter()
+        .find(|c| c.status != Status::Ok)
+    {
+        Some(Check {
+            detail,
+            fix: Some(fix),
+            ..
+        }) => format!("{problem}: {detail} ({fix})").into(),
+        _ => problem.into(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn platform() -> Vec<Check> {
+    linux::checks()
+}
+
+#[cfg(target_os = "linux")]
+fn platform_adapter() -> Vec<Check> {
+    linux::adapter_checks()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn platform() -> Vec<Check> {
+    Vec::new()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn platform_adapter() -> Vec<Check> {
+    Vec::new()
+}
+
+fn build() -> Check {
+    let rustc = env!("MIBAND_RUSTC_VERSION");
+    if rustc.contains("nightly") || rustc.contains("beta") {
+        Check::warn(
+            "Toolchain",
+            format!("built with {rustc}"),
+            "Prefer a stable toolchain: rustup default stable && cargo install --path .",
+        )
+    } else {
+        Check::ok("Toolchain", format!("built with {rustc}"))
+    }
+}
+
+async fn adapter() -> Vec<Check> {
+    let Some(adapter) = Adapter::default().await else {
+        return vec![Check::fail(
+            "Adapter",
+            "no Bluetooth adapter found",
+            if cfg!(target_os = "linux") {
+                "Plug in an adapter and make sure bluetoothd is running: systemctl start bluetooth"
+            } else {
+                "Plug in or enable a Bluetooth adapter in the system settings"
+            },
+        )];
+    };
+    let mut checks = vec![Check::ok("Adapter", "found")];
+
+    checks.push(match adapter.is_available().await {
+        Ok(true) => Check::ok("Power", "adapter is powered on"),
+        Ok(false) => Check::fail(
+            "Power",
+            "adapter is powered off",
+            if cfg!(target_os = "linux") {
+                "Power it on: bluetoothctl power on"
+            } else {
+                "Turn Bluetooth on in the system settings"
+            },
+        ),
+        Err(err) => Check::fail(
+            "Power",
+            format!("cannot query adapter state: {err}"),
+            "Check that the Bluetooth service is running",
+        ),
+    });
+
+    checks.push(match adapter.connected_devices().await {
+        Ok(_) => Check::ok("Permissions", "adapter access granted"),
+        Err(err) if matches!(err.kind(), ErrorKind::NotAuthorized) => Check::fail(
+            "Permissions",
+            format!("access denied: {err}"),
+            if cfg!(target_os = "windows") {
+                "Allow apps to control device radios: Settings > Privacy & security > Radios"
+            } else if cfg!(target_os = "macos") {
+                "Grant Bluetooth access to your terminal: System Settings > Privacy & Security > Bluetooth"
+            } else {
+                "Allow your user in the BlueZ D-Bus policy (/etc/dbus-1/system.d/bluetooth.conf)"
+            },
+        ),
+        Err(err) => Check::warn(
+            "Permissions",
+            format!("could not verify access: {err}"),
+            "Re-run once the adapter is powered on",
+        ),
+    });
+
+    checks
+}
+
+/// Whether `sink` accepts a TCP connection. Only the network is checked, not
+/// credentials or the protocol on top.
+async fn reachable(sink: &Sink) -> Check {
+    let Some((host, port)) = endpoint(&sink.url) else {
+        return Check::fail(
+            sink.name,
+            format!("cannot tell the host and port from {:?}", sink.url),
+            sink.fix,
+        );
+    };
+    match timeout(SINK_TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => Check::ok(sink.name, format!("{host}:{port} is reachable")),
+        Ok(Err(err)) => Check::fail(
+            sink.name,
+            format!("cannot connect to {host}:{port}: {err}"),
+            sink.fix,
+        ),
+        Err(_) => Check::fail(
+            sink.name,
+            format!(
+                "no answer from {host}:{port} within {}s",
+                SINK_TIMEOUT.as_secs()
+            ),
+            sink.fix,
+        ),
+    }
+}
+
+/// Host and port of `url`, with the scheme's default port if it has none.
+fn endpoint(url: &str) -> Option<(&str, u16)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let default = match scheme {
+        "mqtt" => 1883,
+        "http" | "ws" => 80,
+        "https" | "wss" => 443,
+        _ => return None,
+    };
+    let authority = rest.split('/').next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let (host, port) = match host.rsplit_once(':') {
+        // An IPv6 address without a port
+        Some((_, port)) if port.ends_with(']') => (host, default),
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (host, default),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    (!host.is_empty()).then_some((host, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_default_the_port() {
+        assert_eq!(endpoint("mqtt://user:pw@broker"), Some(("broker", 1883)));
+        assert_eq!(
+            endpoint("http://localhost:3000/grafana"),
+            Some(("localhost", 3000))
+        );
+        assert_eq!(
+            endpoint("https://relay.example.com"),
+            Some(("relay.example.com", 443))
+        );
+        assert_eq!(endpoint("http://[::1]:8080"), Some(("::1", 8080)));
+        assert_eq!(endpoint("http://[::1]"), Some(("::1", 80)));
+        assert_eq!(endpoint("localhost:3000"), None);
+        assert_eq!(endpoint("mqtt://broker:port"), None);
+    }
+}
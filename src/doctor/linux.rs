@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::Path;
+
+use super::Check;
+
+/// Processes that usually register a BlueZ pairing agent.
+const AGENT_PROCESSES: &[&str] = &[
+    "bluetoothctl",
+    "bt-agent",
+    "blueman-applet",
+    "gnome-shell",
+    "kded5",
+    "kded6",
+];
+
+pub fn checks() -> Vec<Check> {
+    if let Some(remote) = remote_bus() {
+        return vec![remote];
+    }
+    let mut checks = adapter_checks();
+    checks.push(agent());
+    checks
+}
+
+/// Checks that explain why the adapter is missing or unusable.
+pub fn adapter_checks() -> Vec<Check> {
+    if let Some(remote) = remote_bus() {
+        return vec![remote];
+    }
+    vec![rfkill(), bluetooth_group(), bluetoothd()]
+}
+
+/// Local checks say nothing about a BlueZ instance on another machine.
+fn remote_bus() -> Option<Check> {
+    let address = std::env::var("DBUS_SYSTEM_BUS_ADDRESS").ok()?;
+    Some(Check::warn(
+        "BlueZ",
+        format!("using system bus at {address}; local checks skipped"),
+        "Run `doctor` on the machine hosting the Bluetooth radio",
+    ))
+}
+
+fn read_trimmed(path: impl AsRef<Path>) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_owned())
+}
+
+fn rfkill() -> Check {
+    let Ok(entries) = fs::read_dir("/sys/class/rfkill") else {
+        return Check::ok("rfkill", "no rfkill switches");
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if read_trimmed(path.join("type")).as_deref() != Some("bluetooth") {
+            continue;
+        }
+        if read_trimmed(path.join("hard")).as_deref() == Some("1") {
+            return Check::fail(
+                "rfkill",
+                "Bluetooth is hard-blocked",
+                "Flip the hardware wireless switch or enable Bluetooth in the firmware setup",
+            );
+        }
+        if read_trimmed(path.join("soft")).as_deref() == Some("1") {
+            return Check::fail(
+                "rfkill",
+                "Bluetooth is soft-blocked",
+                "Unblock it: rfkill unblock bluetooth",
+            );
+        }
+    }
+    Check::ok("rfkill", "Bluetooth is not blocked")
+}
+
+/// Value of a `Key:` line in /proc/self/status.
+fn proc_status(key: &str) -> Option<String> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .map(|value| value.trim().to_owned())
+}
+
+fn bluetooth_group() -> Check {
+    let Some(gid) = fs::read_to_string("/etc/group").ok().and_then(|groups| {
+        groups.lines().find_map(|line| {
+            let mut fields = line.split(':');
+            (fields.next()? == "bluetooth").then_some(())?;
+            fields.nth(1)?.parse::<u32>().ok()
+        })
+    }) else {
+        return Check::ok("Group", "this system has no bluetooth group");
+    };
+
+    let is_root = proc_status("Uid")
+        .and_then(|uids| uids.split_whitespace().nth(1).map(|euid| euid == "0"))
+        .unwrap_or(false);
+    let in_group = proc_status("Groups")
+        .map(|groups| {
+            groups
+                .split_whitespace()
+                .any(|group| group.parse::<u32>().ok() == Some(gid))
+        })
+        .unwrap_or(false);
+
+    if is_root || in_group {
+        Check::ok("Group", "user may access the bluetooth group")
+    } else {
+        Check::warn(
+            "Group",
+            "user is not a member of the bluetooth group",
+            "Add yourself and log in again: sudo usermod -aG bluetooth $USER",
+        )
+    }
+}
+
+/// Names of all running processes.
+fn process_names() -> Vec<String> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+        .filter_map(|entry| read_trimmed(entry.path().join("comm")))
+        .collect()
+}
+
+fn bluetoothd() -> Check {
+    if process_names().iter().any(|name| name == "bluetoothd") {
+        Check::ok("BlueZ", "bluetoothd is running")
+    } else {
+        Check::fail(
+            "BlueZ",
+            "bluetoothd is not running",
+            "Start the BlueZ daemon: sudo systemctl enable --now bluetooth",
+        )
+    }
+}
+
+fn agent() -> Check {
+    let names = process_names();
+    match AGENT_PROCESSES
+        .iter()
+        .find(|agent| names.iter().any(|name| name == *agent))
+    {
+        Some(agent) => Check::ok("Agent", format!("pairing agent likely provided by {agent}")),
+        None => Check::warn(
+            "Agent",
+            "no known BlueZ pairing agent is running",
+            "Pairing requests will be rejected; keep `bluetoothctl` open with `agent on` and `default-agent`",
+        ),
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+/// Parse durations like `90`, `30s`, `5m` or `1h30m`. Bare numbers are seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("invalid duration {s:?}: unknown unit {c:?}")),
+        };
+        let value: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid duration {s:?}: missing number before {c:?}"))?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|value| total.checked_add(value))
+            .ok_or_else(|| format!("invalid duration {s:?}: too long"))?;
+        number.clear();
+    }
+    if !number.is_empty() || s.is_empty() {
+        return Err(format!(
+            "invalid duration {s:?}: expected e.g. 30s, 5m or 1h30m"
+        ));
+    }
+    Ok(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid() {
+        let secs = |s| parse_duration(s).map(|duration| duration.as_secs());
+        assert_eq!(secs("90"), Ok(90));
+        assert_eq!(secs(" 30s "), Ok(30));
+        assert_eq!(secs("5m"), Ok(300));
+        assert_eq!(secs("1h30m"), Ok(5400));
+        assert_eq!(secs("2d"), Ok(172_800));
+        assert_eq!(secs("1m1m"), Ok(120));
+    }
+
+    #[test]
+    fn invalid() {
+        for s in ["", "m", "5x", "5m3", "-5", "1.5h"] {
+            assert!(parse_duration(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        let err = parse_duration("18446744073709551615d").unwrap_err();
+        assert!(err.ends_with("too long"), "{err}");
+        let err = parse_duration("18446744073709551615s1s").unwrap_err();
+        assert!(err.ends_with("too long"), "{err}");
+    }
+}
@@ -0,0 +1,500 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::info;
+
+use crate::paths::rfc3339;
+
+/// Seconds between the Unix epoch and the FIT epoch (1989-12-31T00:00:00Z).
+const FIT_EPOCH: u64 = 631_065_600;
+
+/// Activity file formats Strava and Garmin Connect accept.
+#[derive(Clone, Copy)]
+pub enum ExportFormat {
+    Tcx,
+    Fit,
+}
+
+impl ExportFormat {
+    /// Pick the format from the file extension.
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("tcx") => Ok(ExportFormat::Tcx),
+            Some("fit") => Ok(ExportFormat::Fit),
+            _ => Err(format!(
+                "Cannot tell the export format of {}: use .tcx or .fit",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// Collects the heart rate track of a session and writes it as activity
+/// files when the session ends. Each segment becomes a lap, with the timer
+/// paused in between.
+pub struct Exporter {
+    targets: Vec<(PathBuf, ExportFormat)>,
+    points: Vec<(SystemTime, u16)>,
+    /// Indices of the first points of segments after the first.
+    segments: Vec<usize>,
+}
+
+impl Exporter {
+    pub fn new(targets: Vec<(PathBuf, ExportFormat)>) -> Self {
+        Exporter {
+            targets,
+            points: Vec::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, time: SystemTime, bpm: u16) {
+        self.points.push((time, bpm));
+    }
+
+    /// Start a new lap with the next point, e.g. after a system suspend.
+    pub fn next_segment(&mut self) {
+        let start = self.segments.last().copied().unwrap_or(0);
+        if self.points.len() > start {
+            self.segments.push(self.points.len());
+        }
+    }
+
+    /// Write every target and return the paths written. Nothing is written
+    /// for a session without measurements.
+    pub fn finish(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let Some(track) = Track::new(&self.points, &self.segments) else {
+            info!("No measurements, skipping activity export");
+            return Ok(Vec::new());
+        };
+        let mut written = Vec::new();
+        for (path, format) in &self.targets {
+            let contents = match format {
+                ExportFormat::Tcx => track.tcx().into_bytes(),
+                ExportFormat::Fit => track.fit(),
+            };
+            write_atomically(path, &contents)?;
+            info!("Exported activity to {}", path.display());
+            written.push(path.clone());
+        }
+        Ok(written)
+    }
+}
+
+/// Write `contents` to `path` through a synced temporary file, so a crash
+/// leaves either the old file or the complete new one, never a truncated one.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let mut file = std::fs::File::create(&temporary)
+        .map_err(|err| format!("Cannot write {}: {err}", temporary.display()))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)
+        .map_err(|err| format!("Cannot write {}: {err}", path.display()))?;
+    Ok(())
+}
+
+/// Points of a session or one of its laps, in whole seconds since the Unix
+/// epoch.
+struct Lap {
+    points: Range<usize>,
+    start: u64,
+    end: u64,
+    avg: u16,
+    max: u16,
+}
+
+fn secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl Lap {
+    fn new(all: &[(SystemTime, u16)], points: Range<usize>) -> Option<Self> {
+        let slice = &all[points.clone()];
+        let sum: u64 = slice.iter().map(|&(_, bpm)| u64::from(bpm)).sum();
+        Some(Lap {
+            start: secs(slice.first()?.0),
+            end: secs(slice.last()?.0),
+            avg: (sum / slice.len() as u64) as u16,
+            max: slice.iter().map(|&(_, bpm)| bpm).max()?,
+            points,
+        })
+    }
+
+    /// Time the timer ran, in ms.
+    fn timer_ms(&self) -> u32 {
+        span_ms(self.start, self.end)
+    }
+}
+
+/// Milliseconds from `start` to `end` seconds, clamped to what FIT can hold.
+fn span_ms(start: u64, end: u64) -> u32 {
+    end.saturating_sub(start)
+        .saturating_mul(1000)
+        .min(u64::from(u32::MAX)) as u32
+}
+
+/// A session's heart rate track.
+struct Track<'a> {
+    points: &'a [(SystemTime, u16)],
+    total: Lap,
+    laps: Vec<Lap>,
+}
+
+impl<'a> Track<'a> {
+    fn new(points: &'a [(SystemTime, u16)], segments: &[usize]) -> Option<Self> {
+        let bounds: Vec<usize> = std::iter::once(0)
+            .chain(segments.iter().copied())
+            .chain(std::iter::once(points.len()))
+            .collect();
+        Some(Track {
+            points,
+            total: Lap::new(points, 0..points.len())?,
+            laps: bounds
+                .windows(2)
+                .filter_map(|bounds| Lap::new(points, bounds[0]..bounds[1]))
+                .collect(),
+        })
+    }
+
+    /// Training Center XML with one lap per segment.
+    fn tcx(&self) -> String {
+        let time = |secs: u64| rfc3339(UNIX_EPOCH + std::time::Duration::from_secs(secs));
+        let mut xml = String::new();
+        let _ = write!(
+            xml,
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\">\n",
+                "  <Activities>\n",
+                "    <Activity Sport=\"Other\">\n",
+                "      <Id>{}</Id>\n",
+            ),
+            time(self.total.start),
+        );
+        for lap in &self.laps {
+            let _ = write!(
+                xml,
+                concat!(
+                    "      <Lap StartTime=\"{start}\">\n",
+                    "        <TotalTimeSeconds>{duration}</TotalTimeSeconds>\n",
+                    "        <DistanceMeters>0</DistanceMeters>\n",
+                    "        <Calories>0</Calories>\n",
+                    "        <AverageHeartRateBpm><Value>{avg}</Value></AverageHeartRateBpm>\n",
+                    "        <MaximumHeartRateBpm><Value>{max}</Value></MaximumHeartRateBpm>\n",
+                    "        <Intensity>Active</Intensity>\n",
+                    "        <TriggerMethod>Manual</TriggerMethod>\n",
+                    "        <Track>\n",
+                ),
+                start = time(lap.start),
+                duration = lap.end - lap.start,
+                avg = lap.avg,
+                max = lap.max,
+            );
+            for &(time, bpm) in &self.points[lap.points.clone()] {
+                let _ = writeln!(
+                    xml,
+                    "          <Trackpoint><Time>{}</Time><HeartRateBpm><Value>{bpm}</Value></HeartRateBpm></Trackpoint>",
+                    rfc3339(time)
+                );
+            }
+            xml += concat!("        </Track>\n", "      </Lap>\n");
+        }
+        xml += concat!(
+            "    </Activity>\n",
+            "  </Activities>\n",
+            "</TrainingCenterDatabase>\n",
+        );
+        xml
+    }
+
+    /// FIT activity file: file ID, then per segment timer start, one record
+    /// per measurement, timer stop and lap, then session and activity
+    /// summaries.
+    fn fit(&self) -> Vec<u8> {
+        let fit_time = |secs: u64| secs.saturating_sub(FIT_EPOCH) as u32;
+        let (start, end) = (fit_time(self.total.start), fit_time(self.total.end));
+        // total_elapsed_time and total_timer_time are in ms
+        let elapsed = span_ms(start.into(), end.into());
+        let timer = self
+            .laps
+            .iter()
+            .map(|lap| u64::from(lap.timer_ms()))
+            .sum::<u64>()
+            .min(u64::from(u32::MAX)) as u32;
+        let hr = |bpm: u16| bpm.min(255) as u8;
+        let mut fit = Fit::default();
+
+        fit.define(0, 0, &[(0, ENUM), (1, U16), (2, U16), (3, U32Z), (4, U32)]);
+        // Activity file from a development manufacturer
+        fit.data(
+            0,
+            &[
+                &[4],
+                &255u16.to_le_bytes(),
+                &0u16.to_le_bytes(),
+                &1u32.to_le_bytes(),
+                &start.to_le_bytes(),
+            ],
+        );
+
+        fit.define(1, 21, &[(253, U32), (0, ENUM), (1, ENUM)]);
+        fit.define(2, 20, &[(253, U32), (3, U8)]);
+        fit.define(
+            3,
+            19,
+            &[
+                (253, U32),
+                (2, U32),
+                (7, U32),
+                (8, U32),
+                (15, U8),
+                (16, U8),
+                (0, ENUM),
+                (1, ENUM),
+            ],
+        );
+        for lap in &self.laps {
+            let (lap_start, lap_end) = (fit_time(lap.start), fit_time(lap.end));
+            // Timer start
+            fit.data(1, &[&lap_start.to_le_bytes(), &[0], &[0]]);
+            for &(time, bpm) in &self.points[lap.points.clone()] {
+                fit.data(2, &[&fit_time(secs(time)).to_le_bytes(), &[hr(bpm)]]);
+            }
+            // Timer stop all
+            fit.data(1, &[&lap_end.to_le_bytes(), &[0], &[4]]);
+            fit.data(
+                3,
+                &[
+                    &lap_end.to_le_bytes(),
+                    &lap_start.to_le_bytes(),
+                    &lap.timer_ms().to_le_bytes(),
+                    &lap.timer_ms().to_le_bytes(),
+                    &[hr(lap.avg)],
+                    &[hr(lap.max)],
+                    &[9],
+                    &[1],
+                ],
+            );
+        }
+
+        fit.define(
+            4,
+            18,
+            &[
+                (253, U32),
+                (2, U32),
+                (7, U32),
+                (8, U32),
+                (5, ENUM),
+                (6, ENUM),
+                (16, U8),
+                (17, U8),
+                (25, U16),
+                (26, U16),
+                (0, ENUM),
+                (1, ENUM),
+            ],
+        );
+        fit.data(
+            4,
+            &[
+                &end.to_le_bytes(),
+                &start.to_le_bytes(),
+                &elapsed.to_le_bytes(),
+                &timer.to_le_bytes(),
+                &[0],
+                &[0],
+                &[hr(self.total.avg)],
+                &[hr(self.total.max)],
+                &0u16.to_le_bytes(),
+                &(self.laps.len() as u16).to_le_bytes(),
+                &[8],
+                &[1],
+            ],
+        );
+
+        fit.define(
+            5,
+            34,
+            &[
+                (253, U32),
+                (0, U32),
+                (1, U16),
+                (2, ENUM),
+                (3, ENUM),
+                (4, ENUM),
+            ],
+        );
+        fit.data(
+            5,
+            &[
+                &end.to_le_bytes(),
+                &timer.to_le_bytes(),
+                &1u16.to_le_bytes(),
+                &[0],
+                &[26],
+                &[1],
+            ],
+        );
+
+        fit.finish()
+    }
+}
+
+/// FIT base types, as `(size, type byte)`.
+const ENUM: (u8, u8) = (1, 0x00);
+const U8: (u8, u8) = (1, 0x02);
+const U16: (u8, u8) = (2, 0x84);
+const U32: (u8, u8) = (4, 0x86);
+const U32Z: (u8, u8) = (4, 0x8C);
+
+/// Little-endian FIT message writer.
+#[derive(Default)]
+struct Fit {
+    records: Vec<u8>,
+}
+
+impl Fit {
+    /// Definition message binding `local` to the global message `global`
+    /// with the given `(field number, base type)` fields.
+    fn define(&mut self, local: u8, global: u16, fields: &[(u8, (u8, u8))]) {
+        self.records.extend([0x40 | local, 0, 0]);
+        self.records.extend(global.to_le_bytes());
+        self.records.push(fields.len() as u8);
+        for &(number, (size, base_type)) in fields {
+            self.records.extend([number, size, base_type]);
+        }
+    }
+
+    /// Data message for `local`; the values must follow its definition.
+    fn data(&mut self, local: u8, values: &[&[u8]]) {
+        self.records.push(local);
+        for value in values {
+            self.records.extend_from_slice(value);
+        }
+    }
+
+    /// The complete file: header, records and CRC.
+    fn finish(self) -> Vec<u8> {
+        let mut file = vec![14, 0x20];
+        // Profile version 21.32
+        file.extend(2132u16.to_le_bytes());
+        file.extend((self.records.len() as u32).to_le_bytes());
+        file.extend(b".FIT");
+        let header_crc = crc(&file);
+        file.extend(header_crc.to_le_bytes());
+        file.extend(self.records);
+        let file_crc = crc(&file);
+        file.extend(file_crc.to_le_bytes());
+        file
+    }
+}
+
+/// CRC-16 as specified by the FIT protocol.
+fn crc(bytes: &[u8]) -> u16 {
+    const TABLE: [u16; 16] = [
+        0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
+        0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+    ];
+    bytes.iter().fold(0, |mut crc, &byte| {
+        for nibble in [byte & 0x0F, byte >> 4] {
+            let tmp = TABLE[usize::from(crc & 0x0F)];
+            crc = (crc >> 4) & 0x0FFF;
+            crc = crc ^ tmp ^ TABLE[usize::from(nibble)];
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn crc_matches_the_fit_sdk() {
+        // CRC-16/ARC check value
+        assert_eq!(crc(b"123456789"), 0xBB3D);
+        assert_eq!(crc(&[]), 0);
+    }
+
+    #[test]
+    fn definition_and_data_messages() {
+        let mut fit = Fit::default();
+        fit.define(2, 20, &[(253, U32), (3, U8)]);
+        fit.data(2, &[&0x01020304u32.to_le_bytes(), &[72]]);
+        assert_eq!(
+            fit.records,
+            [
+                0x42, 0, 0, 20, 0, 2, 253, 4, 0x86, 3, 1, 0x02, // definition
+                0x02, 0x04, 0x03, 0x02, 0x01, 72, // data
+            ]
+        );
+    }
+
+    #[test]
+    fn header_and_file_crc() {
+        let mut fit = Fit::default();
+        fit.data(0, &[&[1, 2, 3]]);
+        let file = fit.finish();
+        assert_eq!(file.len(), 14 + 4 + 2);
+        assert_eq!(file[0], 14);
+        assert_eq!(&file[4..8], &4u32.to_le_bytes());
+        assert_eq!(&file[8..12], b".FIT");
+        assert_eq!(crc(&file[..14]), 0);
+        assert_eq!(crc(&file), 0);
+    }
+
+    #[test]
+    fn fit_has_a_record_per_point() {
+        let start = FIT_EPOCH + 1_000;
+        let points = [(at(start), 70), (at(start + 1), 300), (at(start + 5), 80)];
+        let track = Track::new(&points, &[2]).unwrap();
+        let file = track.fit();
+        assert_eq!(crc(&file), 0);
+
+        let records = &file[14..file.len() - 2];
+        for (offset, bpm) in [(0u32, 70u8), (1, 255), (5, 80)] {
+            let record = [&[2][..], &(1_000 + offset).to_le_bytes(), &[bpm]].concat();
+            assert!(
+                records.windows(record.len()).any(|window| window == record),
+                "no record for +{offset}s"
+            );
+        }
+    }
+
+    #[test]
+    fn laps_follow_segments() {
+        let points = [(at(100), 60), (at(110), 80), (at(200), 100), (at(230), 90)];
+        let track = Track::new(&points, &[2]).unwrap();
+        assert_eq!(track.laps.len(), 2);
+        assert_eq!((track.laps[0].start, track.laps[0].end), (100, 110));
+        assert_eq!((track.laps[0].avg, track.laps[0].max), (70, 80));
+        assert_eq!(track.laps[1].timer_ms(), 30_000);
+        assert_eq!((track.total.avg, track.total.max), (82, 100));
+
+        assert!(Track::new(&[], &[]).is_none());
+    }
+
+    #[test]
+    fn spans_clamp_to_fit_range() {
+        assert_eq!(span_ms(20, 10), 0);
+        assert_eq!(span_ms(0, u64::from(u32::MAX)), u32::MAX);
+    }
+}
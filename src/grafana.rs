@@ -0,0 +1,73 @@
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use tracing::{info, warn};
+
+use crate::latency::Probe;
+use crate::output::Sample;
+
+/// Pushes measurements to a Grafana Live channel (`stream/<stream>/heart_rate`)
+/// so dashboards update in real time without a database in between.
+pub struct GrafanaLive {
+    tx: SyncSender<(String, Instant)>,
+}
+
+impl GrafanaLive {
+    pub fn new(url: &str, stream: &str, token: Option<String>, latency: Probe) -> Self {
+        let endpoint = format!("{}/api/live/push/{stream}", url.trim_end_matches('/'));
+        let (tx, rx) = mpsc::sync_channel::<(String, Instant)>(64);
+
+        // HTTP is blocking, keep it off the BLE loop
+        thread::spawn(move || {
+            let mut failing = false;
+            for (line, received) in rx {
+                let mut request = ureq::post(&endpoint);
+                if let Some(token) = &token {
+                    request = request.set("Authorization", &format!("Bearer {token}"));
+                }
+                match request.send_string(&line) {
+                    Ok(_) => {
+                        latency.delivered(received);
+                        if failing {
+                            info!("Grafana Live push recovered");
+                            failing = false;
+                        }
+                    }
+                    Err(err) if !failing => {
+                        warn!("Grafana Live push failed: {err}");
+                        failing = true;
+                    }
+                    Err(_) => {}
+                }
+            }
+        });
+
+        GrafanaLive { tx }
+    }
+
+    pub fn push(&self, sample: &Sample) {
+        // Influx line protocol, as expected by the push endpoint
+        let mut line = String::from("heart_rate");
+        if let Some(device) = &sample.device {
+            // Tag values escape commas, equals signs and spaces
+            let device = device
+                .replace(',', "\\,")
+                .replace('=', "\\=")
+                .replace(' ', "\\ ");
+            line += &format!(",device={device}");
+        }
+        line += &format!(
+            " bpm={}i,raw_bpm={}i,quality={}i",
+            sample.bpm, sample.raw_bpm, sample.quality
+        );
+        if let Some(contact) = sample.contact {
+            line += &format!(",contact={contact}");
+        }
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            line += &format!(" {}", now.as_nanos());
+        }
+        // Drop samples rather than stall notifications when Grafana is slow
+        let _ = self.tx.try_send((line, sample.received));
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::output::Sample;
+
+/// Hard cap on memory used by the ring, whatever the retention.
+const MAX_BYTES: usize = 8 * 1024 * 1024;
+
+pub struct HistorySample {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub bpm: u16,
+    /// Name or ID of the device, when streaming from several at once.
+    pub device: Option<Arc<str>>,
+    received: Instant,
+}
+
+/// A sample, or a bucket of them when downsampled.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Point {
+    /// Milliseconds since the Unix epoch (bucket start when downsampled).
+    pub ts: u64,
+    pub bpm: f32,
+    pub min: u16,
+    pub max: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+/// Recent samples kept in memory, bounded by both age and size, independent
+/// of any file output.
+pub struct History {
+    samples: VecDeque<HistorySample>,
+    retention: Duration,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(retention: Duration) -> Self {
+        History {
+            samples: VecDeque::new(),
+            retention,
+            capacity: MAX_BYTES / size_of::<HistorySample>(),
+        }
+    }
+
+    pub fn push(&mut self, sample: &Sample) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        // Share the name with the device's earlier samples
+        let device = sample.device.as_deref().map(|name| {
+            self.samples
+                .iter()
+                .rev()
+                .find_map(|s| s.device.clone().filter(|device| &**device == name))
+                .unwrap_or_else(|| name.into())
+        });
+        self.samples.push_back(HistorySample {
+            timestamp_ms,
+            bpm: sample.bpm,
+            device,
+            received: sample.received,
+        });
+
+        while self.samples.len() > self.capacity
+            || self
+                .samples
+                .front()
+                .is_some_and(|oldest| oldest.received.elapsed() > self.retention)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Samples received within the last `window`, oldest first.
+    pub fn window(&self, window: Duration) -> impl Iterator<Item = &HistorySample> {
+        let start = self
+            .samples
+            .partition_point(|s| s.received.elapsed() > window);
+        self.samples.range(start..)
+    }
+
+    /// The last `window` as points, averaged into `step` buckets unless it is
+    /// zero. Each device gets its own buckets; `device` keeps just one.
+    pub fn points(&self, window: Duration, step: Duration, device: Option<&str>) -> Vec<Point> {
+        let samples = self
+            .window(window)
+            .filter(|sample| device.is_none_or(|device| sample.device.as_deref() == Some(device)));
+        downsample(samples, step.as_millis() as u64)
+    }
+
+    /// BPM of the last `n` samples from `device`, oldest first.
+    pub fn recent(&self, n: usize, device: Option<&str>) -> Vec<u16> {
+        let mut recent: Vec<u16> = self
+            .samples
+            .iter()
+            .rev()
+            .filter(|s| s.device.as_deref() == device)
+            .take(n)
+            .map(|s| s.bpm)
+            .collect();
+        recent.reverse();
+        recent
+    }
+}
+
+/// Average `samples` into buckets of `step_ms` per device, keeping every
+/// sample as is for a step of 0.
+fn downsample<'a>(samples: impl Iterator<Item = &'a HistorySample>, step_ms: u64) -> Vec<Point> {
+    let mut points: Vec<Point> = Vec::new();
+    // Index of the open bucket and its sample count, by device
+    let mut open: Vec<(Option<Arc<str>>, usize, u32)> = Vec::new();
+    for sample in samples {
+        let ts = match step_ms {
+            0 => sample.timestamp_ms,
+            step_ms => sample.timestamp_ms - sample.timestamp_ms % step_ms,
+        };
+        let bucket = open
+            .iter_mut()
+            .find(|(device, _, _)| *device == sample.device)
+            .filter(|(_, index, _)| step_ms != 0 && points[*index].ts == ts);
+        match bucket {
+            Some((_, index, count)) => {
+                let point = &mut points[*index];
+                *count += 1;
+                point.bpm += (sample.bpm as f32 - point.bpm) / *count as f32;
+                point.min = point.min.min(sample.bpm);
+                point.max = point.max.max(sample.bpm);
+            }
+            None => {
+                open.retain(|(device, _, _)| *device != sample.device);
+                open.push((sample.device.clone(), points.len(), 1));
+                points.push(Point {
+                    ts,
+                    bpm: sample.bpm as f32,
+                    min: sample.bpm,
+                    max: sample.bpm,
+                    device: sample.device.as_deref().map(str::to_owned),
+                });
+            }
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp_ms: u64, bpm: u16, device: Option<&str>) -> HistorySample {
+        HistorySample {
+            timestamp_ms,
+            bpm,
+            device: device.map(Into::into),
+            received: Instant::now(),
+        }
+    }
+
+    fn point(ts: u64, bpm: f32, min: u16, max: u16, device: Option<&str>) -> Point {
+        Point {
+            ts,
+            bpm,
+            min,
+            max,
+            device: device.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn step_zero_keeps_every_sample() {
+        let samples = [sample(1_000, 60, None), sample(1_100, 62, None)];
+        assert_eq!(
+            downsample(samples.iter(), 0),
+            [
+                point(1_000, 60.0, 60, 60, None),
+                point(1_100, 62.0, 62, 62, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn averages_into_buckets() {
+        let samples = [
+            sample(1_000, 60, None),
+            sample(1_500, 70, None),
+            sample(1_999, 65, None),
+            sample(2_100, 80, None),
+            sample(4_000, 90, None),
+        ];
+        assert_eq!(
+            downsample(samples.iter(), 1_000),
+            [
+                point(1_000, 65.0, 60, 70, None),
+                point(2_000, 80.0, 80, 80, None),
+                point(4_000, 90.0, 90, 90, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn buckets_per_device() {
+        let samples = [
+            sample(1_000, 60, Some("a")),
+            sample(1_200, 100, Some("b")),
+            sample(1_400, 70, Some("a")),
+            sample(1_600, 110, Some("b")),
+            sample(2_000, 80, Some("a")),
+        ];
+        assert_eq!(
+            downsample(samples.iter(), 1_000),
+            [
+                point(1_000, 65.0, 60, 70, Some("a")),
+                point(1_000, 105.0, 100, 110, Some("b")),
+                point(2_000, 80.0, 80, 80, Some("a")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::recovery::shell;
+
+/// Run each post-session command in turn. They get the summary JSON on stdin
+/// and in `MIBAND_SUMMARY`, and the files the session produced in
+/// `MIBAND_FILES`, separated like `PATH`. Failures are logged, not fatal.
+pub async fn run(commands: &[String], files: &[PathBuf], summary: &Value) {
+    let summary = summary.to_string();
+    let files = std::env::join_paths(files).unwrap_or_default();
+    for command in commands {
+        info!("Running post-session hook: {command}");
+        let child = shell(command)
+            .env("MIBAND_SUMMARY", &summary)
+            .env("MIBAND_FILES", &files)
+            .stdin(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                warn!("Cannot run post-session hook: {err}");
+                continue;
+            }
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            // A hook that ignores stdin may close it early
+            let _ = stdin.write_all(summary.as_bytes()).await;
+        }
+        match child.wait().await {
+            Ok(status) if status.success() => {}
+            Ok(status) => warn!("Post-session hook failed: {status}"),
+            Err(err) => warn!("Cannot run post-session hook: {err}"),
+        }
+    }
+}
@@ -0,0 +1,481 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// A decoded Heart Rate Measurement (0x2A37) notification.
+#[derive(Debug, Clone)]
+pub struct HeartRateMeasurement {
+    pub bpm: u16,
+    /// Width the heart rate value was read in.
+    pub value_format: ValueFormat,
+    /// `None` when the sensor does not support contact detection.
+    pub sensor_contact: Option<bool>,
+    /// Accumulated energy since the last reset, in kJ.
+    pub energy_expended: Option<u16>,
+    /// RR-Interval flag: the packet carries RR data after the fixed fields.
+    pub has_rr_intervals: bool,
+    /// Beat-to-beat intervals since the previous notification, oldest first.
+    pub rr_intervals: Vec<Duration>,
+    /// Non-standard bytes after the standard fields, kept for logging.
+    pub vendor_tail: Vec<u8>,
+    /// The packet filled the whole notification payload, so trailing RR
+    /// intervals may have been cut off. Only set by [`crate::Connection`].
+    pub possibly_truncated: bool,
+    /// The value format flag disagreed with earlier packets and gave an
+    /// impossible reading, so the packet was re-read in this format. Only
+    /// set by [`crate::Connection`].
+    pub flag_drift: Option<ValueFormat>,
+}
+
+/// Width of the Heart Rate Value field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueFormat {
+    U8,
+    U16,
+}
+
+impl ValueFormat {
+    /// The format `flag` announces.
+    fn of(flag: u8) -> Self {
+        if flag & 0b00001 != 0 {
+            ValueFormat::U16
+        } else {
+            ValueFormat::U8
+        }
+    }
+}
+
+impl fmt::Display for ValueFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueFormat::U8 => f.write_str("u8"),
+            ValueFormat::U16 => f.write_str("u16"),
+        }
+    }
+}
+
+/// Workarounds for devices that bend the 0x2A37 layout.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Quirks {
+    /// Number of vendor bytes some clones append after the RR intervals.
+    /// Without RR intervals any trailing bytes are taken as the vendor tail
+    /// regardless.
+    pub vendor_tail: usize,
+    /// Read the heart rate value in this format whatever the flag says.
+    pub value_format: Option<ValueFormat>,
+}
+
+/// A notification that does not match the characteristic's layout.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub reason: &'static str,
+    pub raw: Vec<u8>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, raw: {:02X?}", self.reason, self.raw)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Per-session counters of what the parser saw.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ParseStats {
+    pub notifications: u64,
+    pub malformed: u64,
+    /// Packets re-read because of a flipped value format flag.
+    pub flag_corrections: u64,
+}
+
+impl fmt::Display for ParseStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} notifications, {} malformed, {} flag corrections",
+            self.notifications, self.malformed, self.flag_corrections
+        )
+    }
+}
+
+impl HeartRateMeasurement {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        Self::parse_with(data, &Quirks::default())
+    }
+
+    pub fn parse_with(data: &[u8], quirks: &Quirks) -> Result<Self, ParseError> {
+        let error = |reason| ParseError {
+            reason,
+            raw: data.to_vec(),
+        };
+        let flag = *data.first().ok_or_else(|| error("No flag"))?;
+
+        // Heart Rate Value Format
+        let format = quirks.value_format.unwrap_or(ValueFormat::of(flag));
+        let mut bpm = *data.get(1).ok_or_else(|| error("No heart rate u8"))? as u16;
+        if format == ValueFormat::U16 {
+            bpm |= (*data.get(2).ok_or_else(|| error("No heart rate u16"))? as u16) << 8;
+        }
+
+        // Sensor Contact Supported
+        let mut sensor_contact = None;
+        if flag & 0b00100 != 0 {
+            sensor_contact = Some(flag & 0b00010 != 0)
+        }
+
+        // Energy Expended Status
+        let mut offset = match format {
+            ValueFormat::U8 => 2,
+            ValueFormat::U16 => 3,
+        };
+        let mut energy_expended = None;
+        if flag & 0b01000 != 0 {
+            let energy = data
+                .get(offset..offset + 2)
+                .ok_or_else(|| error("No energy expended"))?;
+            energy_expended = Some(u16::from_le_bytes([energy[0], energy[1]]));
+            offset += 2;
+        }
+
+        // RR-Interval, in 1/1024 s
+        let has_rr_intervals = flag & 0b10000 != 0;
+        let mut rr_intervals = Vec::new();
+        let mut vendor_tail = data[offset..].to_vec();
+        if has_rr_intervals {
+            let end = data
+                .len()
+                .checked_sub(quirks.vendor_tail)
+                .filter(|&end| end >= offset)
+                .ok_or_else(|| error("Shorter than the vendor tail"))?;
+            vendor_tail = data[end..].to_vec();
+            let chunks = data[offset..end].chunks_exact(2);
+            if !chunks.remainder().is_empty() {
+                return Err(error("Odd RR interval length"));
+            }
+            rr_intervals = chunks
+                .map(|rr| {
+                    let rr = u16::from_le_bytes([rr[0], rr[1]]);
+                    Duration::from_nanos(rr as u64 * 1_000_000_000 / 1024)
+                })
+                .collect();
+        }
+
+        Ok(HeartRateMeasurement {
+            bpm,
+            value_format: format,
+            sensor_contact,
+            energy_expended,
+            has_rr_intervals,
+            rr_intervals,
+            vendor_tail,
+            possibly_truncated: false,
+            flag_drift: None,
+        })
+    }
+
+    /// How many RR intervals a notification of `max_payload` bytes holds
+    /// with this packet's layout: after the flags, the u8 or u16 value, the
+    /// energy expended if present and any vendor tail, 2 bytes each.
+    pub fn rr_capacity(&self, max_payload: usize) -> usize {
+        let value = match self.value_format {
+            ValueFormat::U8 => 1,
+            ValueFormat::U16 => 2,
+        };
+        let energy = if self.energy_expended.is_some() { 2 } else { 0 };
+        let tail = if self.has_rr_intervals {
+            self.vendor_tail.len()
+        } else {
+            0
+        };
+        max_payload.saturating_sub(1 + value + energy + tail) / 2
+    }
+}
+
+/// Packets in a row with the same value format before it is taken as the
+/// device's.
+const DRIFT_SETTLE: u8 = 5;
+/// Highest heart rate a sensor could plausibly report.
+const MAX_BPM: u16 = 250;
+/// Largest plausible change from one packet to the next.
+const MAX_JUMP: u16 = 40;
+
+/// Catches firmwares that flip the Heart Rate Value Format flag on some
+/// packets, which misreads the value and shifts every field after it. Once
+/// the device settled on a format, a packet whose flag says otherwise and
+/// reads as nonsense is re-read with the settled format as a quirk.
+#[derive(Debug, Default)]
+pub struct FlagDrift {
+    settled: Option<ValueFormat>,
+    /// Format of the current run of packets and its length, until settled.
+    run: Option<(ValueFormat, u8)>,
+    last_bpm: Option<u16>,
+}
+
+impl FlagDrift {
+    pub fn parse(
+        &mut self,
+        data: &[u8],
+        quirks: &Quirks,
+    ) -> Result<HeartRateMeasurement, ParseError> {
+        let parsed = HeartRateMeasurement::parse_with(data, quirks);
+        // Nothing to correct when the format is pinned
+        let (None, Some(&flag)) = (quirks.value_format, data.first()) else {
+            return parsed;
+        };
+        let flagged = ValueFormat::of(flag);
+
+        if let Some(settled) = self.settled.filter(|&settled| settled != flagged) {
+            let plausible = parsed
+                .as_ref()
+                .is_ok_and(|measurement| self.plausible(measurement.bpm));
+            let pinned = Quirks {
+                value_format: Some(settled),
+                ..*quirks
+            };
+            match HeartRateMeasurement::parse_with(data, &pinned) {
+                Ok(mut measurement) if !plausible && self.plausible(measurement.bpm) => {
+                    measurement.flag_drift = Some(settled);
+                    self.last_bpm = Some(measurement.bpm);
+                    return Ok(measurement);
+                }
+                _ => {}
+            }
+        }
+
+        if self.settled.is_none() {
+            let run = match self.run {
+                Some((format, length)) if format == flagged => length + 1,
+                _ => 1,
+            };
+            self.run = Some((flagged, run));
+            if run >= DRIFT_SETTLE {
+                self.settled = Some(flagged);
+            }
+        }
+        if let Ok(measurement) = &parsed {
+            self.last_bpm = Some(measurement.bpm);
+        }
+        parsed
+    }
+
+    fn plausible(&self, bpm: u16) -> bool {
+        bpm <= MAX_BPM
+            && self
+                .last_bpm
+                .is_none_or(|last| bpm.abs_diff(last) <= MAX_JUMP)
+    }
+}
+
+/// Where the sensor is worn, from the Body Sensor Location (0x2A38)
+/// characteristic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorLocation {
+    Other,
+    Chest,
+    Wrist,
+    Finger,
+    Hand,
+    EarLobe,
+    Foot,
+    /// A value the specification reserves for future use.
+    Reserved(u8),
+}
+
+impl SensorLocation {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        Some(match *data.first()? {
+            0 => SensorLocation::Other,
+            1 => SensorLocation::Chest,
+            2 => SensorLocation::Wrist,
+            3 => SensorLocation::Finger,
+            4 => SensorLocation::Hand,
+            5 => SensorLocation::EarLobe,
+            6 => SensorLocation::Foot,
+            value => SensorLocation::Reserved(value),
+        })
+    }
+}
+
+impl fmt::Display for SensorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SensorLocation::Other => f.write_str("other"),
+            SensorLocation::Chest => f.write_str("chest"),
+            SensorLocation::Wrist => f.write_str("wrist"),
+            SensorLocation::Finger => f.write_str("finger"),
+            SensorLocation::Hand => f.write_str("hand"),
+            SensorLocation::EarLobe => f.write_str("ear lobe"),
+            SensorLocation::Foot => f.write_str("foot"),
+            SensorLocation::Reserved(value) => write!(f, "reserved ({value})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An RR interval in the characteristic's 1/1024 s units.
+    fn rr(units: u64) -> Duration {
+        Duration::from_nanos(units * 1_000_000_000 / 1024)
+    }
+
+    #[test]
+    fn u8_value_with_contact() {
+        let measurement = HeartRateMeasurement::parse(&[0x06, 72]).unwrap();
+        assert_eq!(measurement.bpm, 72);
+        assert_eq!(measurement.sensor_contact, Some(true));
+        assert_eq!(measurement.energy_expended, None);
+        assert!(!measurement.has_rr_intervals);
+        assert!(measurement.rr_intervals.is_empty());
+        assert!(measurement.vendor_tail.is_empty());
+
+        let measurement = HeartRateMeasurement::parse(&[0x04, 72]).unwrap();
+        assert_eq!(measurement.sensor_contact, Some(false));
+        let measurement = HeartRateMeasurement::parse(&[0x00, 72]).unwrap();
+        assert_eq!(measurement.sensor_contact, None);
+    }
+
+    #[test]
+    fn u16_value_energy_and_rr_intervals() {
+        let data = [0x19, 0x2C, 0x01, 0x34, 0x12, 0x00, 0x04, 0x00, 0x02];
+        let measurement = HeartRateMeasurement::parse(&data).unwrap();
+        assert_eq!(measurement.bpm, 300);
+        assert_eq!(measurement.energy_expended, Some(0x1234));
+        assert!(measurement.has_rr_intervals);
+        assert_eq!(measurement.rr_intervals, [rr(1024), rr(512)]);
+        assert!(measurement.vendor_tail.is_empty());
+    }
+
+    #[test]
+    fn rr_intervals_after_u8_value() {
+        let measurement = HeartRateMeasurement::parse(&[0x10, 60, 0x00, 0x04]).unwrap();
+        assert_eq!(measurement.bpm, 60);
+        assert_eq!(measurement.rr_intervals, [Duration::from_secs(1)]);
+
+        // The flag alone, with no room left for intervals
+        let measurement = HeartRateMeasurement::parse(&[0x10, 60]).unwrap();
+        assert!(measurement.has_rr_intervals);
+        assert!(measurement.rr_intervals.is_empty());
+    }
+
+    #[test]
+    fn malformed() {
+        let reason = |data: &[u8]| HeartRateMeasurement::parse(data).unwrap_err().reason;
+        assert_eq!(reason(&[]), "No flag");
+        assert_eq!(reason(&[0x00]), "No heart rate u8");
+        assert_eq!(reason(&[0x01, 60]), "No heart rate u16");
+        assert_eq!(reason(&[0x08, 60, 0x01]), "No energy expended");
+        assert_eq!(reason(&[0x10, 60, 0x00, 0x04, 0x00]), "Odd RR interval length");
+
+        let err = HeartRateMeasurement::parse(&[0x08, 60]).unwrap_err();
+        assert_eq!(err.raw, [0x08, 60]);
+    }
+
+    #[test]
+    fn vendor_tail() {
+        // Without RR intervals whatever follows the fixed fields
+        let measurement = HeartRateMeasurement::parse(&[0x00, 60, 0xAA, 0xBB]).unwrap();
+        assert_eq!(measurement.vendor_tail, [0xAA, 0xBB]);
+
+        // After RR intervals only as many bytes as the quirk says
+        let quirks = Quirks {
+            vendor_tail: 2,
+            ..Quirks::default()
+        };
+        let data = [0x10, 60, 0x00, 0x04, 0xAA, 0xBB];
+        let measurement = HeartRateMeasurement::parse_with(&data, &quirks).unwrap();
+        assert_eq!(measurement.rr_intervals, [Duration::from_secs(1)]);
+        assert_eq!(measurement.vendor_tail, [0xAA, 0xBB]);
+        // and without the quirk the tail is misread as an interval
+        let measurement = HeartRateMeasurement::parse(&data).unwrap();
+        assert_eq!(measurement.rr_intervals.len(), 2);
+
+        let err = HeartRateMeasurement::parse_with(&[0x10, 60, 0xAA], &quirks).unwrap_err();
+        assert_eq!(err.reason, "Shorter than the vendor tail");
+    }
+
+    #[test]
+    fn pinned_value_format() {
+        let quirks = Quirks {
+            value_format: Some(ValueFormat::U8),
+            ..Quirks::default()
+        };
+        let measurement = HeartRateMeasurement::parse_with(&[0x01, 60, 0x10], &quirks).unwrap();
+        assert_eq!(measurement.bpm, 60);
+        assert_eq!(measurement.vendor_tail, [0x10]);
+    }
+
+    #[test]
+    fn flag_drift_rereads_flipped_packets() {
+        let quirks = Quirks::default();
+        let mut drift = FlagDrift::default();
+        for _ in 0..DRIFT_SETTLE {
+            let measurement = drift.parse(&[0x00, 70], &quirks).unwrap();
+            assert_eq!(measurement.flag_drift, None);
+        }
+
+        // Read as u16 this is 4167 bpm
+        let measurement = drift.parse(&[0x01, 71, 0x10], &quirks).unwrap();
+        assert_eq!(measurement.bpm, 71);
+        assert_eq!(measurement.flag_drift, Some(ValueFormat::U8));
+        assert_eq!(measurement.vendor_tail, [0x10]);
+
+        // A flipped packet that reads fine either way is left alone
+        let measurement = drift.parse(&[0x01, 72, 0x00], &quirks).unwrap();
+        assert_eq!(measurement.bpm, 72);
+        assert_eq!(measurement.flag_drift, None);
+    }
+
+    #[test]
+    fn flag_drift_waits_for_a_settled_format() {
+        let quirks = Quirks::default();
+        let mut drift = FlagDrift::default();
+        for _ in 0..DRIFT_SETTLE - 1 {
+            drift.parse(&[0x00, 70], &quirks).unwrap();
+        }
+        let measurement = drift.parse(&[0x01, 71, 0x10], &quirks).unwrap();
+        assert_eq!(measurement.bpm, 0x1047);
+        assert_eq!(measurement.flag_drift, None);
+    }
+
+    #[test]
+    fn flag_drift_respects_a_pinned_format() {
+        let quirks = Quirks {
+            value_format: Some(ValueFormat::U16),
+            ..Quirks::default()
+        };
+        let mut drift = FlagDrift::default();
+        for _ in 0..DRIFT_SETTLE {
+            drift.parse(&[0x00, 70, 0x00], &quirks).unwrap();
+        }
+        let measurement = drift.parse(&[0x00, 71, 0x10], &quirks).unwrap();
+        assert_eq!(measurement.bpm, 0x1047);
+        assert_eq!(measurement.flag_drift, None);
+    }
+
+    #[test]
+    fn rr_capacity_follows_the_layout() {
+        // 20 byte payload of the default 23 byte ATT MTU
+        let measurement = HeartRateMeasurement::parse(&[0x10, 60]).unwrap();
+        assert_eq!(measurement.rr_capacity(20), 9);
+        let measurement = HeartRateMeasurement::parse(&[0x19, 60, 0, 0, 0]).unwrap();
+        assert_eq!(measurement.rr_capacity(20), 7);
+
+        let quirks = Quirks {
+            vendor_tail: 2,
+            ..Quirks::default()
+        };
+        let measurement = HeartRateMeasurement::parse_with(&[0x10, 60, 0xAA, 0xBB], &quirks);
+        assert_eq!(measurement.unwrap().rr_capacity(20), 8);
+        assert_eq!(HeartRateMeasurement::parse(&[0x00, 60]).unwrap().rr_capacity(1), 0);
+    }
+
+    #[test]
+    fn sensor_location() {
+        assert_eq!(SensorLocation::parse(&[2]), Some(SensorLocation::Wrist));
+        assert_eq!(SensorLocation::parse(&[9]), Some(SensorLocation::Reserved(9)));
+        assert_eq!(SensorLocation::parse(&[]), None);
+    }
+}
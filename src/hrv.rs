@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// RR intervals outside this range (ms) are sensor glitches, not beats.
+pub(crate) const PLAUSIBLE_RR_MS: std::ops::RangeInclusive<f64> = 300.0..=2000.0;
+/// An interval differing from the running mean by more than this fraction is
+/// taken for an ectopic beat or a missed/extra detection.
+const MAX_DEVIATION: f64 = 0.2;
+/// Consecutive rejections after which the rate is assumed to have really
+/// changed and the window starts over.
+const MAX_REJECTED: u32 = 5;
+/// Accepted intervals needed before reporting anything.
+const MIN_INTERVALS: usize = 10;
+
+/// Heart rate variability over the window.
+pub struct Hrv {
+    /// Root mean square of successive differences.
+    pub rmssd_ms: f64,
+    /// Standard deviation of the intervals.
+    pub sdnn_ms: f64,
+    pub mean_rr_ms: f64,
+}
+
+/// Keeps the RR intervals of the last `window` of beats, dropping artifacts,
+/// and computes time-domain HRV from them. One engine per connection.
+pub struct HrvEngine {
+    window: Duration,
+    /// Accepted intervals in ms, and whether each directly follows the one
+    /// before it (no rejected interval in between).
+    intervals: VecDeque<(f64, bool)>,
+    total_ms: f64,
+    rejected: u32,
+    /// Whether the last interval seen was accepted.
+    contiguous: bool,
+}
+
+impl HrvEngine {
+    pub fn new(window: Duration) -> Self {
+        HrvEngine {
+            window,
+            intervals: VecDeque::new(),
+            total_ms: 0.0,
+            rejected: 0,
+            contiguous: false,
+        }
+    }
+
+    /// Add the intervals of one notification. Returns the metrics once the
+    /// window holds enough clean intervals, `None` without RR data.
+    pub fn update(&mut self, rr_intervals: &[Duration]) -> Option<Hrv> {
+        for rr in rr_intervals {
+            self.push(rr.as_secs_f64() * 1000.0);
+        }
+        if rr_intervals.is_empty() {
+            return None;
+        }
+        self.compute()
+    }
+
+    fn push(&mut self, rr: f64) {
+        if !PLAUSIBLE_RR_MS.contains(&rr) {
+            self.contiguous = false;
+            return;
+        }
+        if !self.intervals.is_empty() {
+            let mean = self.total_ms / self.intervals.len() as f64;
+            if (rr - mean).abs() > mean * MAX_DEVIATION {
+                self.rejected += 1;
+                self.contiguous = false;
+                if self.rejected < MAX_REJECTED {
+                    return;
+                }
+                self.intervals.clear();
+                self.total_ms = 0.0;
+            }
+        }
+        self.rejected = 0;
+        self.intervals.push_back((rr, self.contiguous));
+        self.total_ms += rr;
+        self.contiguous = true;
+
+        let window_ms = self.window.as_secs_f64() * 1000.0;
+        while self.total_ms > window_ms && self.intervals.len() > 1 {
+            if let Some((old, _)) = self.intervals.pop_front() {
+                self.total_ms -= old;
+            }
+        }
+    }
+
+    fn compute(&self) -> Option<Hrv> {
+        let n = self.intervals.len();
+        if n < MIN_INTERVALS {
+            return None;
+        }
+        let mean = self.total_ms / n as f64;
+        let variance = self
+            .intervals
+            .iter()
+            .map(|(rr, _)| (rr - mean).powi(2))
+            .sum::<f64>()
+            / (n - 1) as f64;
+
+        // Only differences between neighbouring beats count
+        let (sum, count) = self
+            .intervals
+            .iter()
+            .zip(self.intervals.iter().skip(1))
+            .filter(|(_, (_, follows))| *follows)
+            .fold((0.0, 0usize), |(sum, count), ((a, _), (b, _))| {
+                (sum + (b - a).powi(2), count + 1)
+            });
+        if count == 0 {
+            return None;
+        }
+        Some(Hrv {
+            rmssd_ms: (sum / count as f64).sqrt(),
+            sdnn_ms: variance.sqrt(),
+            mean_rr_ms: mean,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(engine: &mut HrvEngine, intervals_ms: &[u64]) -> Option<Hrv> {
+        let intervals: Vec<Duration> = intervals_ms
+            .iter()
+            .map(|&ms| Duration::from_millis(ms))
+            .collect();
+        engine.update(&intervals)
+    }
+
+    #[test]
+    fn rmssd_and_sdnn() {
+        let mut engine = HrvEngine::new(Duration::from_secs(60));
+        let hrv = feed(
+            &mut engine,
+            &[800, 850, 800, 850, 800, 850, 800, 850, 800, 850],
+        )
+        .unwrap();
+        assert!((hrv.rmssd_ms - 50.0).abs() < 1e-9);
+        // Sample standard deviation: sqrt(10 * 25² / 9)
+        assert!((hrv.sdnn_ms - (6250.0f64 / 9.0).sqrt()).abs() < 1e-9);
+        assert!((hrv.mean_rr_ms - 825.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn needs_enough_intervals() {
+        let mut engine = HrvEngine::new(Duration::from_secs(60));
+        assert!(feed(&mut engine, &[800; 9]).is_none());
+        assert!(feed(&mut engine, &[]).is_none());
+        assert!(feed(&mut engine, &[800]).is_some());
+    }
+
+    #[test]
+    fn artifacts_break_the_successive_differences() {
+        let mut engine = HrvEngine::new(Duration::from_secs(60));
+        // The implausible interval is dropped, and the 800 -> 800 step
+        // across it is not a difference between neighbouring beats
+        let hrv = feed(
+            &mut engine,
+            &[800, 850, 800, 850, 800, 2500, 800, 850, 800, 850, 800],
+        )
+        .unwrap();
+        assert!((hrv.rmssd_ms - 50.0).abs() < 1e-9);
+
+        let mut engine = HrvEngine::new(Duration::from_secs(60));
+        // An ectopic beat, more than 20% off the mean
+        let hrv = feed(
+            &mut engine,
+            &[800, 850, 800, 850, 800, 1200, 800, 850, 800, 850, 800],
+        )
+        .unwrap();
+        assert!((hrv.rmssd_ms - 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn restarts_after_a_real_change_of_rate() {
+        let mut engine = HrvEngine::new(Duration::from_secs(60));
+        feed(&mut engine, &[1000; 10]).unwrap();
+        // Rejected until MAX_REJECTED in a row, which starts the window over
+        assert!(feed(&mut engine, &[500; MAX_REJECTED as usize]).is_none());
+        let hrv = feed(&mut engine, &[500; MIN_INTERVALS - 1]).unwrap();
+        assert!((hrv.mean_rr_ms - 500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn window_drops_old_intervals() {
+        let mut engine = HrvEngine::new(Duration::from_secs(5));
+        // Only six 800 ms intervals fit in 5 s
+        assert!(feed(&mut engine, &[800; 20]).is_none());
+    }
+}
@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::duration::parse_duration;
+use crate::history::History;
+use crate::session::SessionTimer;
+
+#[derive(Clone)]
+struct ApiState {
+    history: Arc<Mutex<History>>,
+    session: Arc<SessionTimer>,
+}
+
+/// Serves the HTTP API in the background.
+pub async fn serve(
+    addr: SocketAddr,
+    history: Arc<Mutex<History>>,
+    session: Arc<SessionTimer>,
+) -> Result<(), Box<dyn Error>> {
+    let app = Router::new()
+        .route("/history", get(get_history))
+        .route("/session/stop", post(stop_session))
+        .with_state(ApiState { history, session });
+    let listener = TcpListener::bind(addr).await?;
+    info!("HTTP API listening on http://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        if let Err(err) = axum::serve(listener, app).await {
+            warn!("HTTP API stopped: {err}");
+        }
+    });
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// How far back to go, e.g. `10m`.
+    window: Option<String>,
+    /// Bucket size for server-side downsampling, e.g. `5s`.
+    step: Option<String>,
+    /// Only this device, with --all-devices.
+    device: Option<String>,
+}
+
+async fn get_history(
+    State(ApiState { history, .. }): State<ApiState>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    let window = parse_duration(query.window.as_deref().unwrap_or("5m"));
+    let step = query.step.as_deref().map(parse_duration).transpose();
+    let (window, step) = match (window, step) {
+        (Ok(window), Ok(step)) => (window, step),
+        (Err(err), _) | (_, Err(err)) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let step = step.unwrap_or_default();
+    let device = query.device.as_deref();
+    let points = history.lock().unwrap().points(window, step, device);
+
+    // Browser overlays are usually served from another origin
+    ([(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")], Json(points)).into_response()
+}
+
+#[derive(Deserialize)]
+struct StopQuery {
+    /// Time left in the session, e.g. `45m`; right away if missing.
+    after: Option<String>,
+}
+
+/// Same as --duration: end the session after the given time.
+async fn stop_session(
+    State(ApiState { session, .. }): State<ApiState>,
+    Query(query): Query<StopQuery>,
+) -> Response {
+    let stopped = parse_duration(query.after.as_deref().unwrap_or("0s"))
+        .and_then(|after| session.stop_after(after));
+    match stopped {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, err).into_response(),
+    }
+}
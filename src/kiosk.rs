@@ -0,0 +1,188 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+
+const FRAMEBUFFER: &str = "/dev/fb0";
+const FRAMEBUFFER_SYSFS: &str = "/sys/class/graphics/fb0";
+
+/// Width of one sparkline bar plus its gap, in pixels.
+const BAR_PITCH: usize = 6;
+
+#[derive(Clone, Copy)]
+struct Color(u8, u8, u8);
+
+const BACKGROUND: Color = Color(0, 0, 0);
+const GREY: Color = Color(90, 90, 90);
+const GREEN: Color = Color(0, 200, 80);
+const ORANGE: Color = Color(255, 150, 0);
+const RED: Color = Color(230, 30, 30);
+
+/// Same thresholds as the Python GUI.
+fn color_for(bpm: u16) -> Color {
+    match bpm {
+        0..80 => GREEN,
+        80..=100 => ORANGE,
+        _ => RED,
+    }
+}
+
+/// Segments a..g of a seven-segment digit, one bit each.
+const DIGITS: [u8; 10] = [
+    0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110, 0b1101101, 0b1111101, 0b0000111,
+    0b1111111, 0b1101111,
+];
+const DASH: u8 = 0b1000000;
+
+/// Full-screen heart rate display drawn straight to the Linux framebuffer, for a
+/// dedicated Raspberry Pi with a small screen.
+pub struct Kiosk {
+    fb: File,
+    width: usize,
+    height: usize,
+    stride: usize,
+    bytes_per_pixel: usize,
+    frame: Vec<u8>,
+}
+
+fn sysfs(name: &str) -> Result<String, Box<dyn Error>> {
+    Ok(fs::read_to_string(format!("{FRAMEBUFFER_SYSFS}/{name}"))?
+        .trim()
+        .to_owned())
+}
+
+impl Kiosk {
+    pub fn open() -> Result<Self, Box<dyn Error>> {
+        if !cfg!(target_os = "linux") {
+            return Err("Kiosk mode needs a Linux framebuffer".into());
+        }
+        let size = sysfs("virtual_size")?;
+        let (width, height) = size
+            .split_once(',')
+            .ok_or_else(|| format!("Unexpected framebuffer size {size:?}"))?;
+        let (width, height): (usize, usize) = (width.parse()?, height.parse()?);
+        let bytes_per_pixel = match sysfs("bits_per_pixel")?.parse::<usize>()? {
+            bpp @ (16 | 32) => bpp / 8,
+            bpp => return Err(format!("Unsupported framebuffer depth: {bpp} bits").into()),
+        };
+        let stride = sysfs("stride")?.parse()?;
+        let fb = OpenOptions::new()
+            .write(true)
+            .open(FRAMEBUFFER)
+            .map_err(|err| {
+                format!("Cannot open {FRAMEBUFFER} (is the user in the video group?): {err}")
+            })?;
+
+        let mut kiosk = Kiosk {
+            fb,
+            width,
+            height,
+            stride,
+            bytes_per_pixel,
+            frame: vec![0; stride * height],
+        };
+        kiosk.draw(None, &[])?;
+        Ok(kiosk)
+    }
+
+    /// Number of samples the sparkline has room for.
+    pub fn bars(&self) -> usize {
+        self.width / BAR_PITCH
+    }
+
+    /// Show a new measurement above the sparkline of `recent` values.
+    pub fn update(&mut self, bpm: u16, recent: &[u16]) -> Result<(), Box<dyn Error>> {
+        self.draw(Some(bpm), recent)
+    }
+
+    /// Show dashes while no device is streaming.
+    pub fn disconnected(&mut self, recent: &[u16]) -> Result<(), Box<dyn Error>> {
+        self.draw(None, recent)
+    }
+
+    fn draw(&mut self, bpm: Option<u16>, recent: &[u16]) -> Result<(), Box<dyn Error>> {
+        self.fill(0, 0, self.width, self.height, BACKGROUND);
+
+        // Big digits in the top 60%
+        let digits_height = self.height * 6 / 10;
+        let digit_height = digits_height * 8 / 10;
+        let digit_width = digit_height / 2;
+        let gap = digit_width / 4;
+        let (segments, color) = match bpm {
+            Some(bpm) => (
+                bpm.to_string()
+                    .bytes()
+                    .map(|b| DIGITS[(b - b'0') as usize])
+                    .collect::<Vec<_>>(),
+                color_for(bpm),
+            ),
+            None => (vec![DASH; 3], GREY),
+        };
+        let total_width = segments.len() * (digit_width + gap) - gap;
+        let mut x = self.width.saturating_sub(total_width) / 2;
+        let y = (digits_height - digit_height) / 2;
+        for mask in segments {
+            self.digit(x, y, digit_width, digit_height, mask, color);
+            x += digit_width + gap;
+        }
+
+        // Sparkline in the bottom 40%
+        let top = digits_height;
+        let height = self.height - top - self.height / 20;
+        let recent = &recent[recent.len().saturating_sub(self.bars())..];
+        if let (Some(&min), Some(&max)) = (recent.iter().min(), recent.iter().max()) {
+            let (min, max) = (min.saturating_sub(5), max.saturating_add(5));
+            for (i, &value) in recent.iter().enumerate() {
+                let bar = height * (value - min) as usize / (max - min) as usize;
+                self.fill(
+                    i * BAR_PITCH,
+                    top + height - bar,
+                    BAR_PITCH - 1,
+                    bar.max(1),
+                    color_for(value),
+                );
+            }
+        }
+
+        self.fb.seek(SeekFrom::Start(0))?;
+        self.fb.write_all(&self.frame)?;
+        Ok(())
+    }
+
+    fn digit(&mut self, x: usize, y: usize, w: usize, h: usize, mask: u8, color: Color) {
+        let t = (w / 5).max(1);
+        let half = h / 2;
+        let segments = [
+            (0, 0, w, t),               // a
+            (w - t, 0, t, half),        // b
+            (w - t, half, t, h - half), // c
+            (0, h - t, w, t),           // d
+            (0, half, t, h - half),     // e
+            (0, 0, t, half),            // f
+            (0, half - t / 2, w, t),    // g
+        ];
+        for (bit, (sx, sy, sw, sh)) in segments.into_iter().enumerate() {
+            if mask & (1 << bit) != 0 {
+                self.fill(x + sx, y + sy, sw, sh, color);
+            }
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, w: usize, h: usize, Color(r, g, b): Color) {
+        let (pixel, len) = match self.bytes_per_pixel {
+            // RGB565
+            2 => {
+                let v = ((r as u16 >> 3) << 11) | ((g as u16 >> 2) << 5) | (b as u16 >> 3);
+                let [lo, hi] = v.to_le_bytes();
+                ([lo, hi, 0, 0], 2)
+            }
+            // XRGB8888, little endian
+            _ => ([b, g, r, 0xFF], 4),
+        };
+        for row in y..(y + h).min(self.height) {
+            for col in x..(x + w).min(self.width) {
+                let offset = row * self.stride + col * self.bytes_per_pixel;
+                self.frame[offset..offset + self.bytes_per_pixel].copy_from_slice(&pixel[..len]);
+            }
+        }
+    }
+}
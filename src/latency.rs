@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::info;
+
+/// Samples kept per sink for the percentiles.
+const WINDOW: usize = 1024;
+/// Samples kept per sink for the session summary's p99.
+const SESSION_WINDOW: usize = 16 * 1024;
+/// Upper bounds of the histogram buckets exposed to Prometheus.
+const BUCKETS: [Duration; 12] = [
+    Duration::from_micros(100),
+    Duration::from_micros(250),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_micros(2500),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_secs(1),
+];
+
+#[derive(Default)]
+struct Sink {
+    /// Since the last report.
+    recent: VecDeque<Duration>,
+    /// The latest of the session.
+    session: VecDeque<Duration>,
+    /// Deliveries per bucket of [`BUCKETS`], the last one for slower ones.
+    buckets: [u64; BUCKETS.len() + 1],
+    sum: Duration,
+}
+
+/// Time from a notification arriving to it being delivered by each sink.
+#[derive(Default)]
+pub struct Latency {
+    sinks: Mutex<BTreeMap<&'static str, Sink>>,
+}
+
+/// Cumulative latency histogram of one sink since the start.
+pub struct Histogram {
+    pub sink: &'static str,
+    /// Deliveries that took at most each bound, Prometheus' `le` buckets
+    /// without `+Inf`.
+    pub buckets: Vec<(Duration, u64)>,
+    pub count: u64,
+    pub sum: Duration,
+}
+
+impl Latency {
+    pub fn probe(self: &Arc<Self>, sink: &'static str) -> Probe {
+        Probe {
+            latency: self.clone(),
+            sink,
+        }
+    }
+
+    fn record(&self, sink: &'static str, latency: Duration) {
+        let mut sinks = self.sinks.lock().unwrap();
+        let sink = sinks.entry(sink).or_default();
+        for (samples, window) in [
+            (&mut sink.recent, WINDOW),
+            (&mut sink.session, SESSION_WINDOW),
+        ] {
+            if samples.len() == window {
+                samples.pop_front();
+            }
+            samples.push_back(latency);
+        }
+        let bucket = BUCKETS.partition_point(|&bound| bound < latency);
+        sink.buckets[bucket] += 1;
+        sink.sum += latency;
+    }
+
+    /// Print p50/p99/max per sink and start over.
+    pub fn report(&self) {
+        let mut sinks = self.sinks.lock().unwrap();
+        for (name, sink) in sinks.iter_mut() {
+            let sorted = sorted(&sink.recent);
+            sink.recent.clear();
+            let Some(&max) = sorted.last() else {
+                continue;
+            };
+            info!(
+                "Latency {name}: p50 {:?}, p99 {:?}, max {max:?} ({} samples)",
+                percentile(&sorted, 50),
+                percentile(&sorted, 99),
+                sorted.len()
+            );
+        }
+    }
+
+    /// p99 per sink over the session, or its latest [`SESSION_WINDOW`]
+    /// deliveries.
+    pub fn p99(&self) -> Vec<(&'static str, Duration)> {
+        let sinks = self.sinks.lock().unwrap();
+        sinks
+            .iter()
+            .filter(|(_, sink)| !sink.session.is_empty())
+            .map(|(&name, sink)| (name, percentile(&sorted(&sink.session), 99)))
+            .collect()
+    }
+
+    pub fn histograms(&self) -> Vec<Histogram> {
+        let sinks = self.sinks.lock().unwrap();
+        sinks
+            .iter()
+            .map(|(&name, sink)| {
+                let mut count = 0;
+                let buckets = BUCKETS
+                    .iter()
+                    .zip(sink.buckets)
+                    .map(|(&bound, n)| {
+                        count += n;
+                        (bound, count)
+                    })
+                    .collect();
+                Histogram {
+                    sink: name,
+                    buckets,
+                    count: count + sink.buckets[BUCKETS.len()],
+                    sum: sink.sum,
+                }
+            })
+            .collect()
+    }
+}
+
+fn sorted(samples: &VecDeque<Duration>) -> Vec<Duration> {
+    let mut sorted: Vec<_> = samples.iter().copied().collect();
+    sorted.sort();
+    sorted
+}
+
+/// The `p`th percentile of non-empty `sorted`.
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    sorted[(sorted.len() - 1) * p / 100]
+}
+
+/// Records deliveries for one sink.
+#[derive(Clone)]
+pub struct Probe {
+    latency: Arc<Latency>,
+    sink: &'static str,
+}
+
+impl Probe {
+    pub fn delivered(&self, received: Instant) {
+        self.latency.record(self.sink, received.elapsed());
+    }
+}
@@ -0,0 +1,35 @@
+//! Read heart rate from a Xiaomi Smart Band, or any standard BLE heart rate
+//! monitor, through the Heart Rate Service.
+//!
+//! ```no_run
+//! use futures_lite::StreamExt;
+//! use miband_heart_rate::HeartRateClient;
+//!
+//! # async fn demo() -> Result<(), Box<dyn std::error::Error>> {
+//! let adapter = bluest::Adapter::default().await.ok_or("no adapter")?;
+//! adapter.wait_available().await?;
+//! let client = HeartRateClient::new(adapter);
+//! let device = client.scan(None).await?;
+//! let connection = client.connect(&device).await?;
+//! let mut measurements = connection.measurements().await?;
+//! while let Some(measurement) = measurements.next().await {
+//!     println!("{:?}", measurement?.bpm);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod hrm;
+pub mod timeout;
+pub mod xiaomi;
+
+mod client;
+
+use bluest::{btuuid::bluetooth_uuid_from_u16, Uuid};
+
+pub use client::{Connection, DeviceFilter, HeartRateClient, Nearby};
+
+/// Heart Rate Service.
+pub const HRS_UUID: Uuid = bluetooth_uuid_from_u16(0x180D);
+/// Heart Rate Measurement characteristic.
+pub const HRM_UUID: Uuid = bluetooth_uuid_from_u16(0x2A37);
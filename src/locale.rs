@@ -0,0 +1,112 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+
+/// kJ in a kcal.
+const KJ_PER_KCAL: f64 = 4.184;
+
+/// Languages that write `1,5` rather than `1.5`.
+const DECIMAL_COMMA: [&str; 24] = [
+    "bg", "ca", "cs", "da", "de", "el", "es", "et", "fi", "fr", "hr", "hu", "id", "it", "lt", "lv",
+    "nb", "nl", "pl", "pt", "ro", "ru", "sv", "tr",
+];
+/// Regions whose clocks show AM and PM.
+const TWELVE_HOUR: [&str; 7] = [
+    "en_US", "en_CA", "en_AU", "en_NZ", "en_PH", "en_IN", "hi_IN",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EnergyUnit {
+    Kj,
+    Kcal,
+}
+
+impl EnergyUnit {
+    pub fn convert(self, kj: f64) -> f64 {
+        match self {
+            EnergyUnit::Kj => kj,
+            EnergyUnit::Kcal => kj / KJ_PER_KCAL,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            EnergyUnit::Kj => "kJ",
+            EnergyUnit::Kcal => "kcal",
+        }
+    }
+}
+
+/// How numbers, times and energy are shown to people: in console lines and
+/// the session summary. Machine formats (JSON, TCX, FIT) do not change, the
+/// CSV only takes the energy unit.
+#[derive(Clone, Copy)]
+pub struct Locale {
+    decimal_comma: bool,
+    twelve_hour: bool,
+    pub energy: EnergyUnit,
+}
+
+impl Locale {
+    /// `tag` such as `de_DE` or `en-US.UTF-8`; without one the usual
+    /// environment variables are consulted.
+    pub fn new(tag: Option<&str>, energy: EnergyUnit) -> Self {
+        let tag = tag.map(str::to_owned).or_else(|| {
+            ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find(|value| !value.is_empty())
+        });
+        let tag = tag.unwrap_or_default().replace('-', "_");
+        let tag = tag.split(['.', '@']).next().unwrap_or_default();
+        let language = tag
+            .split('_')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        Locale {
+            decimal_comma: DECIMAL_COMMA.contains(&language.as_str()),
+            twelve_hour: TWELVE_HOUR
+                .iter()
+                .any(|region| region.eq_ignore_ascii_case(tag)),
+            energy,
+        }
+    }
+
+    /// `value` with `places` decimals.
+    pub fn decimal(&self, value: f64, places: usize) -> String {
+        let text = format!("{value:.places$}");
+        if self.decimal_comma {
+            text.replace('.', ",")
+        } else {
+            text
+        }
+    }
+
+    /// `energy` given in kJ, in the chosen unit with its symbol.
+    pub fn energy(&self, kj: f64) -> String {
+        let places = match self.energy {
+            EnergyUnit::Kj => 0,
+            EnergyUnit::Kcal => 1,
+        };
+        format!(
+            "{} {}",
+            self.decimal(self.energy.convert(kj), places),
+            self.energy.symbol()
+        )
+    }
+
+    /// Time of day of `time` in UTC, `14:05` or `2:05 PM`.
+    pub fn clock(&self, time: SystemTime) -> String {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let (hour, minute) = (secs / 3600 % 24, secs / 60 % 60);
+        if self.twelve_hour {
+            let period = if hour < 12 { "AM" } else { "PM" };
+            format!("{}:{minute:02} {period}", (hour + 11) % 12 + 1)
+        } else {
+            format!("{hour:02}:{minute:02}")
+        }
+    }
+}
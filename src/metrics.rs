@@ -0,0 +1,221 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::latency::Latency;
+use crate::output::Sample;
+
+/// Stand-in for "no value yet" in the atomics below.
+const UNSET: i64 = -1;
+
+/// Counters and gauges for the Prometheus exporter.
+pub struct Metrics {
+    bpm: AtomicI64,
+    contact: AtomicI64,
+    battery: AtomicI64,
+    connected: AtomicI64,
+    connections: AtomicU64,
+    notifications: AtomicU64,
+    malformed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            bpm: AtomicI64::new(UNSET),
+            contact: AtomicI64::new(UNSET),
+            battery: AtomicI64::new(UNSET),
+            connected: AtomicI64::new(0),
+            connections: AtomicU64::new(0),
+            notifications: AtomicU64::new(0),
+            malformed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn measurement(&self, sample: &Sample) {
+        self.bpm.store(sample.bpm as i64, Ordering::Relaxed);
+        self.contact.store(
+            sample.contact.map_or(UNSET, |contact| contact as i64),
+            Ordering::Relaxed,
+        );
+        self.battery.store(
+            sample.battery.map_or(UNSET, |battery| battery as i64),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Count a device as connected until the returned guard is dropped.
+    pub fn connection(self: &Arc<Self>) -> Connected {
+        self.connected.fetch_add(1, Ordering::Relaxed);
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        Connected(self.clone())
+    }
+
+    /// Number of devices streaming right now.
+    pub fn connected(&self) -> i64 {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// A notification arrived; `malformed` if it could not be parsed.
+    pub fn notification(&self, malformed: bool) {
+        self.notifications.fetch_add(1, Ordering::Relaxed);
+        if malformed {
+            self.malformed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A connection ended for any reason but the session shutting down.
+    pub fn dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counter values for the session summary.
+    pub fn totals(&self) -> Totals {
+        Totals {
+            notifications: self.notifications.load(Ordering::Relaxed),
+            malformed: self.malformed.load(Ordering::Relaxed),
+            dropped_connections: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Prometheus text exposition format.
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: Option<i64>| {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+            if let Some(value) = value {
+                let _ = writeln!(out, "{name} {value}");
+            }
+        };
+        let gauge = |value: &AtomicI64| Some(value.load(Ordering::Relaxed)).filter(|&v| v != UNSET);
+        let counter = |value: &AtomicU64| Some(value.load(Ordering::Relaxed) as i64);
+
+        metric(
+            "heart_rate_bpm",
+            "gauge",
+            "Last heart rate measurement, after calibration.",
+            gauge(&self.bpm),
+        );
+        metric(
+            "sensor_contact",
+            "gauge",
+            "1 if the sensor reports skin contact, 0 if not.",
+            gauge(&self.contact),
+        );
+        metric(
+            "battery_level_percent",
+            "gauge",
+            "Battery level of the device.",
+            gauge(&self.battery),
+        );
+        metric(
+            "ble_connected",
+            "gauge",
+            "Number of connected heart rate devices.",
+            Some(self.connected.load(Ordering::Relaxed)),
+        );
+        metric(
+            "ble_reconnects_total",
+            "counter",
+            "Connections after the first one.",
+            Some(self.connections.load(Ordering::Relaxed).saturating_sub(1) as i64),
+        );
+        metric(
+            "hrm_notifications_total",
+            "counter",
+            "Heart rate notifications received.",
+            counter(&self.notifications),
+        );
+        metric(
+            "hrm_dropped_notifications_total",
+            "counter",
+            "Notifications dropped because they could not be parsed.",
+            counter(&self.malformed),
+        );
+        out
+    }
+}
+
+/// See [`Metrics::totals`].
+pub struct Totals {
+    pub notifications: u64,
+    pub malformed: u64,
+    /// Connections that were lost, taken over or failed, not counting shutdown.
+    pub dropped_connections: u64,
+}
+
+/// See [`Metrics::connection`].
+pub struct Connected(Arc<Metrics>);
+
+impl Drop for Connected {
+    fn drop(&mut self) {
+        self.0.connected.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Per-sink delivery latency as a Prometheus histogram.
+fn render_latency(latency: &Latency) -> String {
+    let name = "output_latency_seconds";
+    let mut out = format!(
+        "# HELP {name} Time from a notification arriving to an output delivering it.\n\
+         # TYPE {name} histogram\n"
+    );
+    for histogram in latency.histograms() {
+        let sink = histogram.sink;
+        for (bound, count) in &histogram.buckets {
+            let le = bound.as_secs_f64();
+            let _ = writeln!(out, "{name}_bucket{{sink=\"{sink}\",le=\"{le}\"}} {count}");
+        }
+        let count = histogram.count;
+        let _ = writeln!(out, "{name}_bucket{{sink=\"{sink}\",le=\"+Inf\"}} {count}");
+        let sum = histogram.sum.as_secs_f64();
+        let _ = writeln!(out, "{name}_sum{{sink=\"{sink}\"}} {sum}");
+        let _ = writeln!(out, "{name}_count{{sink=\"{sink}\"}} {count}");
+    }
+    out
+}
+
+/// Serves `GET /metrics` in the background.
+pub async fn serve(
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    latency: Arc<Latency>,
+) -> Result<(), Box<dyn Error>> {
+    let app = Router::new()
+        .route("/metrics", get(get_metrics))
+        .with_state((metrics, latency));
+    let listener = TcpListener::bind(addr).await?;
+    info!(
+        "Prometheus metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    tokio::spawn(async move {
+        if let Err(err) = axum::serve(listener, app).await {
+            warn!("Metrics endpoint stopped: {err}");
+        }
+    });
+    Ok(())
+}
+
+async fn get_metrics(
+    State((metrics, latency)): State<(Arc<Metrics>, Arc<Latency>)>,
+) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render() + &render_latency(&latency),
+    )
+}
@@ -0,0 +1,202 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::output::Sample;
+
+/// Wait before polling the broker again after a connection error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Publishes measurements to an MQTT broker:
+///
+/// - `<topic>`: the current BPM, retained
+/// - `<topic>/json`: every sample as JSON
+/// - `<topic>/availability`: `online` while a device streams
+///
+/// plus Home Assistant discovery so the band shows up as a sensor. With
+/// several devices each gets its own `<topic>/<device>` and sensor.
+pub struct Mqtt {
+    client: AsyncClient,
+    topic: String,
+    /// Whether `online` has been published on the current broker connection.
+    online: Arc<AtomicBool>,
+    /// Discovery configs of the devices seen so far, by device.
+    discoveries: Arc<Mutex<Vec<(Option<String>, (String, String))>>>,
+}
+
+impl Mqtt {
+    /// Connect to `url` (`mqtt://[user:password@]host[:port]`). The broker
+    /// connection is kept up in the background, independently of Bluetooth.
+    pub fn connect(url: &str, topic: &str) -> Result<Self, Box<dyn Error>> {
+        let rest = url
+            .strip_prefix("mqtt://")
+            .ok_or_else(|| format!("MQTT URL must start with mqtt://: {url:?}"))?;
+        let (credentials, host) = match rest.trim_end_matches('/').rsplit_once('@') {
+            Some((credentials, host)) => (Some(credentials), host),
+            None => (None, rest.trim_end_matches('/')),
+        };
+        let (host, port) = match host.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse()?),
+            None => (host, 1883),
+        };
+
+        let availability = format!("{topic}/availability");
+        let mut options = MqttOptions::new(client_id(topic), host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(
+            &availability,
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(credentials) = credentials {
+            let (user, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+            options.set_credentials(user, password);
+        }
+
+        let (client, mut events) = AsyncClient::new(options, 64);
+        let online = Arc::new(AtomicBool::new(false));
+        let discoveries: Arc<Mutex<Vec<_>>> = Arc::default();
+        {
+            let client = client.clone();
+            let online = online.clone();
+            let discoveries = discoveries.clone();
+            tokio::spawn(async move {
+                let mut failing = false;
+                loop {
+                    match events.poll().await {
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            if failing {
+                                info!("MQTT broker reconnected");
+                                failing = false;
+                            }
+                            // Republish in case the broker lost retained messages
+                            for (_, (topic, payload)) in discoveries.lock().unwrap().iter() {
+                                let _ = client.try_publish(
+                                    topic,
+                                    QoS::AtLeastOnce,
+                                    true,
+                                    payload.clone(),
+                                );
+                            }
+                            online.store(false, Ordering::Relaxed);
+                        }
+                        Ok(_) => {}
+                        Err(err) => {
+                            if !failing {
+                                warn!("MQTT connection failed: {err}");
+                                failing = true;
+                            }
+                            tokio::time::sleep(RECONNECT_DELAY).await;
+                        }
+                    }
+                }
+            });
+        }
+
+        Ok(Mqtt {
+            client,
+            topic: topic.to_owned(),
+            online,
+            discoveries,
+        })
+    }
+
+    pub fn publish(&self, sample: &Sample, json: &str) {
+        if !self.online.swap(true, Ordering::Relaxed) {
+            self.availability("online");
+        }
+        let device = sample.device.as_deref();
+        let topic = match device {
+            Some(device) => format!("{}/{}", self.topic, slug(device)),
+            None => self.topic.clone(),
+        };
+        {
+            let mut discoveries = self.discoveries.lock().unwrap();
+            if !discoveries
+                .iter()
+                .any(|(seen, _)| seen.as_deref() == device)
+            {
+                let (config_topic, payload) = discovery(&self.topic, device);
+                let _ =
+                    self.client
+                        .try_publish(&config_topic, QoS::AtLeastOnce, true, payload.clone());
+                discoveries.push((device.map(str::to_owned), (config_topic, payload)));
+            }
+        }
+        // Drop samples rather than stall notifications while the broker is away
+        let _ = self
+            .client
+            .try_publish(&topic, QoS::AtMostOnce, true, sample.bpm.to_string());
+        let _ = self.client.try_publish(
+            format!("{topic}/json"),
+            QoS::AtMostOnce,
+            false,
+            json.to_owned(),
+        );
+    }
+
+    pub fn disconnected(&self) {
+        self.online.store(false, Ordering::Relaxed);
+        self.availability("offline");
+    }
+
+    fn availability(&self, state: &str) {
+        let _ = self.client.try_publish(
+            format!("{}/availability", self.topic),
+            QoS::AtLeastOnce,
+            true,
+            state,
+        );
+    }
+}
+
+/// `name` with anything but ASCII letters and digits replaced, fit for
+/// topics and IDs.
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Derived from the topic so several instances can share a broker.
+fn client_id(topic: &str) -> String {
+    format!("miband_{}", slug(topic))
+}
+
+/// Home Assistant MQTT discovery config for the heart rate sensor of
+/// `device`, or of the single device.
+fn discovery(topic: &str, device: Option<&str>) -> (String, String) {
+    let (id, state_topic, name) = match device {
+        Some(device) => (
+            format!("{}_{}", client_id(topic), slug(device)),
+            format!("{topic}/{}", slug(device)),
+            device,
+        ),
+        None => (client_id(topic), topic.to_owned(), "Mi Band"),
+    };
+    let config = json!({
+        "name": "Heart rate",
+        "unique_id": format!("{id}_bpm"),
+        "state_topic": state_topic,
+        "availability_topic": format!("{topic}/availability"),
+        "unit_of_measurement": "bpm",
+        "state_class": "measurement",
+        "icon": "mdi:heart-pulse",
+        "json_attributes_topic": format!("{state_topic}/json"),
+        "device": {
+            "identifiers": [id],
+            "name": name,
+            "model": "Heart rate monitor",
+        },
+    });
+    (
+        format!("homeassistant/sensor/{id}/config"),
+        config.to_string(),
+    )
+}
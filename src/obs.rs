@@ -0,0 +1,418 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::time::{sleep_until, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tracing::{info, warn};
+
+use crate::output::Sample;
+
+/// Wait before reconnecting to OBS after the connection drops.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How long the beat filter stays on for each beat.
+const PULSE: Duration = Duration::from_millis(120);
+/// Beats keep pulsing on the predicted rhythm for this many intervals after
+/// the last prediction, through notifications that carried no RR intervals.
+const FREEWHEEL_BEATS: u32 = 3;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A filter on an OBS source, `SOURCE:FILTER`.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    source: String,
+    filter: String,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((source, filter)) if !source.is_empty() && !filter.is_empty() => Ok(Filter {
+                source: source.to_owned(),
+                filter: filter.to_owned(),
+            }),
+            _ => Err(format!("expected SOURCE:FILTER, got {s:?}")),
+        }
+    }
+}
+
+/// A filter that is on while the heart rate is in a zone, `ZONE=SOURCE:FILTER`.
+#[derive(Clone, Debug)]
+pub struct ZoneFilter {
+    zone: u8,
+    filter: Filter,
+}
+
+impl FromStr for ZoneFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (zone, filter) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected ZONE=SOURCE:FILTER, got {s:?}"))?;
+        let zone = zone.trim().trim_start_matches(['Z', 'z']);
+        let zone = match zone.parse() {
+            Ok(zone @ 1..=5) => zone,
+            _ => return Err(format!("zone must be 1 to 5, got {zone:?}")),
+        };
+        Ok(ZoneFilter {
+            zone,
+            filter: filter.parse()?,
+        })
+    }
+}
+
+pub struct ObsOptions {
+    pub password: Option<String>,
+    /// Text source that shows the BPM.
+    pub text: Option<String>,
+    /// Filter switched on briefly on every predicted beat.
+    pub beat_filter: Option<Filter>,
+    pub zone_filters: Vec<ZoneFilter>,
+    /// Minimum time between text updates.
+    pub min_interval: Duration,
+}
+
+/// What the scene should show, synced to OBS whenever it changes.
+#[derive(Clone, Default, PartialEq)]
+struct State {
+    text: Option<String>,
+    zone: Option<u8>,
+    /// Predicted next beat and the spacing of the ones after it.
+    beat: Option<(SystemTime, Duration)>,
+}
+
+/// Drives an OBS scene over obs-websocket (v5, OBS 28 and later): a text
+/// source shows the BPM, filters follow the zone and pulse on the beat. The
+/// connection is kept up in the background and the scene is brought up to
+/// date on every reconnect.
+pub struct Obs {
+    tx: watch::Sender<State>,
+    text: bool,
+    min_interval: Duration,
+    last_text: Option<std::time::Instant>,
+}
+
+impl Obs {
+    /// Connect to `url` (`ws://localhost:4455`).
+    pub fn connect(url: &str, options: ObsOptions) -> Result<Self, Box<dyn Error>> {
+        if !url.starts_with("ws://") && !url.starts_with("wss://") {
+            return Err(format!("OBS URL must start with ws:// or wss://: {url:?}").into());
+        }
+        let (tx, mut rx) = watch::channel(State::default());
+        let obs = Obs {
+            tx,
+            text: options.text.is_some(),
+            min_interval: options.min_interval,
+            last_text: None,
+        };
+        let target = Target {
+            url: url.to_owned(),
+            password: options.password,
+            text: options.text,
+            beat_filter: options.beat_filter,
+            zone_filters: options.zone_filters,
+        };
+        tokio::spawn(async move {
+            let mut failing = false;
+            loop {
+                match drive(&target, &mut rx, &mut failing).await {
+                    // Nothing left to show
+                    Ok(()) => return,
+                    Err(err) if !failing => {
+                        warn!("OBS connection failed: {err}");
+                        failing = true;
+                    }
+                    Err(_) => {}
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        });
+        Ok(obs)
+    }
+
+    pub fn measurement(&mut self, sample: &Sample) {
+        let text = self.text
+            && self
+                .last_text
+                .is_none_or(|last| last.elapsed() >= self.min_interval);
+        if text {
+            self.last_text = Some(std::time::Instant::now());
+        }
+        self.tx.send_if_modified(|state| {
+            let mut next = state.clone();
+            if text {
+                next.text = Some(sample.bpm.to_string());
+            }
+            next.zone = sample.zone;
+            if let Some(beat) = &sample.beat {
+                next.beat = Some((beat.next, beat.interval));
+            }
+            let modified = next != *state;
+            *state = next;
+            modified
+        });
+    }
+}
+
+struct Target {
+    url: String,
+    password: Option<String>,
+    text: Option<String>,
+    beat_filter: Option<Filter>,
+    zone_filters: Vec<ZoneFilter>,
+}
+
+/// Keep the scene in sync with the state until the connection drops.
+async fn drive(
+    target: &Target,
+    rx: &mut watch::Receiver<State>,
+    failing: &mut bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (stream, _) = connect_async(target.url.as_str()).await?;
+    let (sink, mut source) = stream.split();
+    let mut requests = Requests { sink, next_id: 0 };
+    identify(target, &mut requests.sink, &mut source).await?;
+    if *failing {
+        info!("OBS reconnected");
+        *failing = false;
+    }
+
+    if let Some(filter) = &target.beat_filter {
+        requests.filter(filter, false).await?;
+    }
+    // Nothing is known about the scene after connecting
+    let mut shown: Option<State> = None;
+    rx.mark_changed();
+    let mut beat: Option<(Instant, Duration, u32)> = None;
+    let mut pulse_off: Option<Instant> = None;
+    // Report each kind of failed request once, not on every measurement
+    let mut reported = HashSet::new();
+    loop {
+        let wake = [beat.map(|(at, ..)| at), pulse_off]
+            .into_iter()
+            .flatten()
+            .min();
+        tokio::select! {
+            changed = rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+                let state = rx.borrow_and_update().clone();
+                let old = shown.as_ref();
+                if let (Some(source), Some(text)) = (&target.text, &state.text) {
+                    if old.is_none_or(|old| old.text.as_ref() != Some(text)) {
+                        let settings = json!({ "text": text });
+                        requests
+                            .send(
+                                "SetInputSettings",
+                                json!({ "inputName": source, "inputSettings": settings }),
+                            )
+                            .await?;
+                    }
+                }
+                for zone_filter in &target.zone_filters {
+                    let on = state.zone == Some(zone_filter.zone);
+                    if old.is_none_or(|old| (old.zone == Some(zone_filter.zone)) != on) {
+                        requests.filter(&zone_filter.filter, on).await?;
+                    }
+                }
+                if let (Some(_), Some((next, interval))) = (&target.beat_filter, state.beat) {
+                    if old.is_none_or(|old| old.beat != state.beat) {
+                        let ahead = next.duration_since(SystemTime::now()).unwrap_or_default();
+                        beat = Some((Instant::now() + ahead, interval, FREEWHEEL_BEATS));
+                    }
+                }
+                shown = Some(state);
+            }
+            () = sleep_until(wake.unwrap_or_else(Instant::now)), if wake.is_some() => {
+                // Beats are only scheduled with a beat filter
+                let (now, filter) = (Instant::now(), target.beat_filter.as_ref().unwrap());
+                if pulse_off.is_some_and(|off| off <= now) {
+                    requests.filter(filter, false).await?;
+                    pulse_off = None;
+                }
+                if let Some((at, interval, left)) = beat.filter(|&(at, ..)| at <= now) {
+                    requests.filter(filter, true).await?;
+                    pulse_off = Some(now + PULSE);
+                    beat = (left > 1).then(|| (at + interval, interval, left - 1));
+                }
+            }
+            incoming = source.next() => match incoming {
+                Some(Ok(Message::Text(text))) => {
+                    let response: Value = serde_json::from_str(text.as_str())?;
+                    let status = &response["d"]["requestStatus"];
+                    if response["op"] == 7 && status["result"] == false {
+                        let request = response["d"]["requestType"].as_str().unwrap_or_default();
+                        let comment = status["comment"].as_str().unwrap_or("no reason given");
+                        if reported.insert(format!("{request} {comment}")) {
+                            warn!("OBS rejected {request}: {comment}");
+                        }
+                    }
+                }
+                Some(Ok(Message::Close(frame))) => {
+                    let reason = frame
+                        .map(|frame| frame.reason.as_str().to_owned())
+                        .unwrap_or_default();
+                    return Err(format!("OBS closed the connection {reason}").trim_end().into());
+                }
+                None => return Err("OBS closed the connection".into()),
+                Some(Err(err)) => return Err(err.into()),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Answer OBS's Hello with an Identify, authenticating if it asks to.
+async fn identify(
+    target: &Target,
+    sink: &mut SplitSink<Socket, Message>,
+    source: &mut SplitStream<Socket>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let hello = next_json(source).await?;
+    let mut identify = json!({ "rpcVersion": 1, "eventSubscriptions": 0 });
+    if let Some(auth) = hello["d"].get("authentication") {
+        let password = target
+            .password
+            .as_deref()
+            .ok_or("OBS asks for a password, see --obs-password")?;
+        let salt = auth["salt"].as_str().unwrap_or_default();
+        let challenge = auth["challenge"].as_str().unwrap_or_default();
+        identify["authentication"] = authentication(password, salt, challenge).into();
+    }
+    let message = json!({ "op": 1, "d": identify });
+    sink.send(Message::text(message.to_string())).await?;
+    match next_json(source).await {
+        Ok(identified) if identified["op"] == 2 => Ok(()),
+        Ok(other) => Err(format!("unexpected message from OBS: {other}").into()),
+        Err(err) => Err(format!("OBS refused to identify us, check --obs-password ({err})").into()),
+    }
+}
+
+async fn next_json(
+    source: &mut SplitStream<Socket>,
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    loop {
+        match source.next().await {
+            Some(Ok(Message::Text(text))) => return Ok(serde_json::from_str(text.as_str())?),
+            Some(Ok(Message::Close(frame))) => {
+                let reason = frame
+                    .map(|frame| frame.reason.as_str().to_owned())
+                    .unwrap_or_default();
+                return Err(format!("connection closed {reason}").trim_end().into());
+            }
+            None => return Err("connection closed".into()),
+            Some(Err(err)) => return Err(err.into()),
+            Some(Ok(_)) => {}
+        }
+    }
+}
+
+/// The obs-websocket authentication string:
+/// `base64(sha256(base64(sha256(password + salt)) + challenge))`.
+fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let secret = base64(&Sha256::digest(format!("{password}{salt}")));
+    base64(&Sha256::digest(format!("{secret}{challenge}")))
+}
+
+/// Standard base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | u32::from(byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+struct Requests {
+    sink: SplitSink<Socket, Message>,
+    next_id: u64,
+}
+
+impl Requests {
+    async fn send(
+        &mut self,
+        request_type: &str,
+        data: Value,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.next_id += 1;
+        let message = json!({
+            "op": 6,
+            "d": {
+                "requestType": request_type,
+                "requestId": self.next_id.to_string(),
+                "requestData": data,
+            },
+        });
+        self.sink.send(Message::text(message.to_string())).await?;
+        Ok(())
+    }
+
+    async fn filter(
+        &mut self,
+        filter: &Filter,
+        enabled: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let data = json!({
+            "sourceName": filter.source,
+            "filterName": filter.filter,
+            "filterEnabled": enabled,
+        });
+        self.send("SetSourceFilterEnabled", data).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authenticates_like_obs() {
+        // Example from the obs-websocket protocol documentation
+        assert_eq!(
+            base64(b"supersecretpassword"),
+            "c3VwZXJzZWNyZXRwYXNzd29yZA=="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b""), "");
+        let auth = authentication(
+            "supersecretpassword",
+            "lM1GncleQOaCu9lT1yeUZhFYnqhsLLP1G5lAGo3ixaI=",
+            "+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY=",
+        );
+        assert_eq!(auth, "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4=");
+    }
+
+    #[test]
+    fn parses_zone_filters() {
+        let zone_filter: ZoneFilter = "Z4=Webcam:Red tint".parse().unwrap();
+        assert_eq!(zone_filter.zone, 4);
+        assert_eq!(zone_filter.filter.source, "Webcam");
+        assert_eq!(zone_filter.filter.filter, "Red tint");
+        assert!("6=Webcam:Red".parse::<ZoneFilter>().is_err());
+        assert!("4=Webcam".parse::<ZoneFilter>().is_err());
+    }
+}
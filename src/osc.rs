@@ -0,0 +1,73 @@
+use std::error::Error;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+/// Sends the current BPM as an OSC message over UDP, e.g. to VRChat avatar
+/// parameters.
+pub struct Osc {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    path: String,
+    float: bool,
+    min_interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl Osc {
+    pub fn new(
+        addr: SocketAddr,
+        path: String,
+        float: bool,
+        min_interval: Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !path.starts_with('/') {
+            return Err(format!("OSC path must start with '/': {path:?}").into());
+        }
+        let local: SocketAddr = if addr.is_ipv4() {
+            "0.0.0.0:0".parse()?
+        } else {
+            "[::]:0".parse()?
+        };
+        Ok(Osc {
+            socket: UdpSocket::bind(local)?,
+            addr,
+            path,
+            float,
+            min_interval,
+            last_sent: None,
+        })
+    }
+
+    pub fn send(&mut self, bpm: u16) {
+        if self
+            .last_sent
+            .is_some_and(|last| last.elapsed() < self.min_interval)
+        {
+            return;
+        }
+        self.last_sent = Some(Instant::now());
+
+        let mut packet = Vec::with_capacity(self.path.len() + 12);
+        pad_string(&mut packet, &self.path);
+        if self.float {
+            pad_string(&mut packet, ",f");
+            packet.extend_from_slice(&(bpm as f32).to_be_bytes());
+        } else {
+            pad_string(&mut packet, ",i");
+            packet.extend_from_slice(&(bpm as i32).to_be_bytes());
+        }
+        // UDP has no connection to lose; a missing receiver is not an error
+        if let Err(err) = self.socket.send_to(&packet, self.addr) {
+            warn!("OSC send failed: {err}");
+        }
+    }
+}
+
+/// OSC strings are null terminated and padded to a multiple of 4 bytes.
+fn pad_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    packet.resize(packet.len() + padding, 0);
+}
@@ -0,0 +1,337 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use miband_heart_rate::hrm::SensorLocation;
+use serde_json::{json, Value};
+
+use crate::alerts::Alerts;
+use crate::beat::Beat;
+use crate::clock::SessionClock;
+use crate::export::Exporter;
+use crate::grafana::GrafanaLive;
+use crate::history::History;
+use crate::hrv::Hrv;
+use crate::kiosk::Kiosk;
+use crate::latency::Latency;
+use crate::metrics::Metrics;
+use crate::mqtt::Mqtt;
+use crate::obs::Obs;
+use crate::osc::Osc;
+use crate::record::Recorder;
+use crate::relay::Relay;
+use crate::session::SessionStats;
+use crate::speech::Announcer;
+use crate::tui::Tui;
+use crate::workout::Guide;
+use crate::ws::WsServer;
+
+/// One heart rate notification, after calibration.
+pub struct Sample {
+    pub bpm: u16,
+    /// Value as reported by the device, before calibration.
+    pub raw_bpm: u16,
+    pub contact: Option<bool>,
+    pub quality: u8,
+    /// Accumulated energy in kJ, if the device reports it.
+    pub energy_expended: Option<u16>,
+    pub rr_intervals: Vec<Duration>,
+    /// The notification filled the whole ATT payload, so trailing RR
+    /// intervals may have been cut off.
+    pub possibly_truncated: bool,
+    /// Battery level in percent, if known.
+    pub battery: Option<u8>,
+    /// Name or ID of the device, when streaming from several at once.
+    pub device: Option<String>,
+    /// Where the sensor is worn, if the device tells.
+    pub sensor_location: Option<SensorLocation>,
+    /// Predicted next heartbeat, when the device sends RR intervals.
+    pub beat: Option<Beat>,
+    /// Variability over the HRV window, once it holds enough clean beats.
+    pub hrv: Option<Hrv>,
+    /// Heart rate zone (1–5) with --max-hr, `None` below zone 1 or without.
+    pub zone: Option<u8>,
+    /// When the notification arrived.
+    pub received: Instant,
+}
+
+impl Sample {
+    /// The object sent to structured outputs.
+    pub fn to_json(&self) -> Value {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        let rr_ms: Vec<f64> = self
+            .rr_intervals
+            .iter()
+            .map(|rr| rr.as_secs_f64() * 1000.0)
+            .collect();
+        let (next_beat_ts, beat_interval_ms) = match &self.beat {
+            Some(beat) => (
+                beat.next
+                    .duration_since(UNIX_EPOCH)
+                    .ok()
+                    .map(|next| next.as_millis() as u64),
+                Some(beat.interval.as_secs_f64() * 1000.0),
+            ),
+            None => (None, None),
+        };
+        let mut json = json!({
+            "bpm": self.bpm,
+            "raw_bpm": self.raw_bpm,
+            "contact": self.contact,
+            "quality": self.quality,
+            "energy_kj": self.energy_expended,
+            "rr_ms": rr_ms,
+            "possibly_truncated": self.possibly_truncated,
+            "next_beat_ts": next_beat_ts,
+            "beat_interval_ms": beat_interval_ms,
+            "rmssd_ms": self.hrv.as_ref().map(|hrv| hrv.rmssd_ms),
+            "sdnn_ms": self.hrv.as_ref().map(|hrv| hrv.sdnn_ms),
+            "mean_rr_ms": self.hrv.as_ref().map(|hrv| hrv.mean_rr_ms),
+            "battery": self.battery,
+            "sensor_location": self.sensor_location.map(|location| location.to_string()),
+            "zone": self.zone,
+            "ts": ts,
+        });
+        if let Some(device) = &self.device {
+            json["device"] = device.as_str().into();
+        }
+        json
+    }
+}
+
+/// Everything a measurement is forwarded to besides the console.
+pub struct Outputs {
+    pub history: Arc<Mutex<History>>,
+    pub kiosk: Option<Kiosk>,
+    pub tui: Option<Tui>,
+    pub grafana: Option<GrafanaLive>,
+    pub nodered: Option<WsServer>,
+    /// WebSocket server for browser overlays.
+    pub overlay: Option<WsServer>,
+    pub recorder: Option<Recorder>,
+    pub exporter: Option<Exporter>,
+    pub osc: Option<Osc>,
+    pub obs: Option<Obs>,
+    pub mqtt: Option<Mqtt>,
+    pub relay: Option<Relay>,
+    /// Print one JSON object per measurement on stdout.
+    pub json_lines: bool,
+    /// Print sentences for screen readers on stdout.
+    pub announcer: Option<Announcer>,
+    pub alerts: Option<Alerts>,
+    pub workout: Option<Guide>,
+    /// A workout cue asks for the band to vibrate; cleared by whoever does.
+    pub buzz: bool,
+    pub latency: Arc<Latency>,
+    pub metrics: Arc<Metrics>,
+    pub stats: SessionStats,
+    /// Timeline for the recording and activity exports.
+    pub clock: SessionClock,
+}
+
+impl Outputs {
+    pub fn new(history: History) -> Self {
+        Outputs {
+            history: Arc::new(Mutex::new(history)),
+            kiosk: None,
+            tui: None,
+            grafana: None,
+            nodered: None,
+            overlay: None,
+            recorder: None,
+            exporter: None,
+            osc: None,
+            obs: None,
+            mqtt: None,
+            relay: None,
+            json_lines: false,
+            announcer: None,
+            alerts: None,
+            workout: None,
+            buzz: false,
+            latency: Arc::default(),
+            metrics: Arc::default(),
+            stats: SessionStats::default(),
+            clock: SessionClock::default(),
+        }
+    }
+
+    pub fn measurement(&mut self, sample: &Sample) -> Result<(), Box<dyn Error>> {
+        let recent = {
+            let mut history = self.history.lock().unwrap();
+            history.push(sample);
+            let bars = self.kiosk.as_ref().map(Kiosk::bars);
+            bars.max(self.tui.as_ref().map(Tui::bars))
+                .map(|bars| history.recent(bars, sample.device.as_deref()))
+        };
+
+        self.metrics.measurement(sample);
+        self.stats.push(
+            sample.device.as_deref(),
+            sample.bpm,
+            sample.zone,
+            sample.energy_expended,
+            sample.received,
+        );
+        if let (Some(kiosk), Some(recent)) = (&mut self.kiosk, &recent) {
+            kiosk.update(sample.bpm, recent)?;
+            self.latency.probe("kiosk").delivered(sample.received);
+        }
+        if let (Some(tui), Some(recent)) = (&mut self.tui, &recent) {
+            tui.update(sample, recent)?;
+        }
+        if let Some(announcer) = &mut self.announcer {
+            for sentence in announcer.measurement(sample) {
+                println!("{sentence}");
+            }
+        }
+        let alerts = self
+            .alerts
+            .as_mut()
+            .map(|alerts| alerts.measurement(sample));
+        for alert in alerts.into_iter().flatten() {
+            self.event(
+                "alert",
+                sample.device.as_deref(),
+                json!({ "alert": alert.kind.to_string(), "bpm": alert.bpm, "message": alert.message }),
+            );
+        }
+        let cues = self
+            .workout
+            .as_mut()
+            .map(|guide| guide.measurement(sample.zone, sample.received));
+        for cue in cues.into_iter().flatten() {
+            if self.announcer.is_some() {
+                println!("{}", cue.message);
+            }
+            self.buzz |= cue.urgent;
+            self.event(cue.event, sample.device.as_deref(), cue.fields);
+        }
+        if let Some(grafana) = &self.grafana {
+            grafana.push(sample);
+        }
+        if self.recorder.is_some() || self.exporter.is_some() {
+            let time = self.clock.stamp(sample.received);
+            if let Some(recorder) = &mut self.recorder {
+                recorder.push(sample, time)?;
+            }
+            if let Some(exporter) = &mut self.exporter {
+                exporter.push(time, sample.bpm);
+            }
+        }
+        if let Some(osc) = &mut self.osc {
+            osc.send(sample.bpm);
+            self.latency.probe("osc").delivered(sample.received);
+        }
+        if let Some(obs) = &mut self.obs {
+            obs.measurement(sample);
+        }
+        if self.json_lines
+            || self.nodered.is_some()
+            || self.overlay.is_some()
+            || self.mqtt.is_some()
+            || self.relay.is_some()
+        {
+            let message = sample.to_json().to_string();
+            if self.json_lines {
+                println!("{message}");
+            }
+            if let Some(mqtt) = &self.mqtt {
+                mqtt.publish(sample, &message);
+            }
+            if let Some(relay) = &self.relay {
+                relay.send(&message);
+            }
+            for server in [&self.nodered, &self.overlay].into_iter().flatten() {
+                server.send(message.clone(), sample.received);
+            }
+        }
+        Ok(())
+    }
+
+    /// Announce something that happened, such as `{"event":"preempted"}`, to
+    /// the streaming JSON outputs. Events are not retained for new clients.
+    pub fn event(&self, event: &str, device: Option<&str>, mut fields: Value) {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        fields["event"] = event.into();
+        if let Some(device) = device {
+            fields["device"] = device.into();
+        }
+        fields["ts"] = ts.into();
+        let message = fields.to_string();
+        if self.json_lines {
+            println!("{message}");
+        }
+        for server in [&self.nodered, &self.overlay].into_iter().flatten() {
+            server.send_transient(message.clone(), Instant::now());
+        }
+    }
+
+    /// `device` stopped streaming, `None` with a single device.
+    pub fn disconnected(&mut self, device: Option<&str>) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        // With several devices the others may still be streaming
+        if self.metrics.connected() == 0 {
+            self.latency.report();
+            if let Some(mqtt) = &self.mqtt {
+                mqtt.disconnected();
+            }
+        }
+        if let Some(announcer) = &mut self.announcer {
+            println!("{}", announcer.disconnected(device));
+        }
+        if let Some(kiosk) = &mut self.kiosk {
+            let recent = self.history.lock().unwrap().recent(kiosk.bars(), device);
+            kiosk.disconnected(&recent)?;
+        }
+        if let Some(tui) = &mut self.tui {
+            let recent = self.history.lock().unwrap().recent(tui.bars(), device);
+            tui.disconnected(&recent)?;
+        }
+        Ok(())
+    }
+
+    /// Start a new segment after the machine was suspended for `asleep`:
+    /// the recording goes on in a new file, activity exports in a new lap,
+    /// and the gap is left out of the summary.
+    pub fn resumed(&mut self, asleep: Duration) -> Result<(), Box<dyn Error>> {
+        self.clock.resumed();
+        self.stats.suspended(asleep);
+        if let Some(exporter) = &mut self.exporter {
+            exporter.next_segment();
+        }
+        self.event("resumed", None, json!({ "suspended_s": asleep.as_secs() }));
+        match &mut self.recorder {
+            Some(recorder) => recorder.next_segment(),
+            None => Ok(()),
+        }
+    }
+
+    /// Finalize the session's exports: the recording is written out and
+    /// closed and activity files are written, live outputs keep going.
+    /// Returns the recording's files and the activity files.
+    pub fn end_session(&mut self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        self.flush()?;
+        let mut files = match self.recorder.take() {
+            Some(recorder) => recorder.files().to_vec(),
+            None => Vec::new(),
+        };
+        if let Some(exporter) = self.exporter.take() {
+            files.extend(exporter.finish()?);
+        }
+        Ok(files)
+    }
+
+    /// Write out anything buffered, e.g. before exiting.
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.flush()?;
+        }
+        Ok(())
+    }
+}
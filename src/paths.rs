@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+/// Expands output path templates such as `~/hr/{date}/{session_id}/` for one
+/// session. Placeholders use the session's start time in UTC:
+/// `{date}` (`2026-10-16`), `{time}` (`142233`) and `{session_id}`
+/// (`20261016-142233`).
+pub struct SessionPaths {
+    date: String,
+    time: String,
+    session_id: String,
+    dir: Option<PathBuf>,
+    /// The part of the output directory that stays the same across sessions,
+    /// where the `latest` pointers go. Only with an output directory.
+    base: Option<PathBuf>,
+}
+
+impl SessionPaths {
+    /// `dir` is where relative output files go, itself a template.
+    pub fn new(started: SystemTime, dir: Option<&str>) -> Self {
+        let secs = started
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let (year, month, day) = civil_from_days((secs / 86400) as i64);
+        let (hour, minute, second) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+        let mut paths = SessionPaths {
+            date: format!("{year:04}-{month:02}-{day:02}"),
+            time: format!("{hour:02}{minute:02}{second:02}"),
+            session_id: format!("{year:04}{month:02}{day:02}-{hour:02}{minute:02}{second:02}"),
+            dir: None,
+            base: None,
+        };
+        if let Some(dir) = dir {
+            // Everything up to the directory holding the first placeholder
+            let fixed = &dir[..dir.find('{').unwrap_or(dir.len())];
+            let fixed = match fixed.rfind(['/', '\\']) {
+                Some(end) if fixed.len() < dir.len() => &fixed[..=end],
+                None if fixed.len() < dir.len() => ".",
+                _ => fixed,
+            };
+            paths.base = Some(paths.expand(fixed));
+            paths.dir = Some(paths.expand(dir));
+        }
+        paths
+    }
+
+    /// Expand the placeholders and a leading `~`.
+    pub fn expand(&self, template: &str) -> PathBuf {
+        let expanded = template
+            .replace("{date}", &self.date)
+            .replace("{time}", &self.time)
+            .replace("{session_id}", &self.session_id);
+        match expanded.strip_prefix('~') {
+            Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+                match dirs::home_dir() {
+                    Some(home) => home.join(rest.trim_start_matches(['/', '\\'])),
+                    None => PathBuf::from(expanded),
+                }
+            }
+            _ => PathBuf::from(expanded),
+        }
+    }
+
+    /// Where an output file given as `template` goes, with its directory
+    /// created. Relative paths land in the output directory, if there is one.
+    pub fn resolve(&self, template: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.expand(&template.to_string_lossy());
+        let path = match &self.dir {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path,
+        };
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)
+                .map_err(|err| format!("Cannot create {}: {err}", parent.display()))?;
+        }
+        Ok(path)
+    }
+
+    /// Point `latest.json` in the base output directory at this session's
+    /// files and summary, and on Unix the `latest` symlink at its directory.
+    /// Both are replaced atomically, so readers never see a half-written one.
+    /// Does nothing without an output directory.
+    pub fn publish_latest(&self, files: &[PathBuf], summary: &Value) -> Result<(), Box<dyn Error>> {
+        let Some(base) = &self.base else {
+            return Ok(());
+        };
+        let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+        let latest = json!({
+            "session_id": self.session_id,
+            "dir": self.dir.as_deref().map(|dir| absolute(dir).display().to_string()),
+            "files": files
+                .iter()
+                .map(|file| absolute(file).display().to_string())
+                .collect::<Vec<_>>(),
+            "summary": summary,
+        });
+        std::fs::create_dir_all(base)?;
+        let target = base.join("latest.json");
+        let temporary = base.join("latest.json.tmp");
+        std::fs::write(&temporary, serde_json::to_string_pretty(&latest)? + "\n")?;
+        std::fs::rename(&temporary, &target)
+            .map_err(|err| format!("Cannot write {}: {err}", target.display()))?;
+
+        #[cfg(unix)]
+        if let Some(dir) = self.dir.as_deref().filter(|&dir| dir != base.as_path()) {
+            let link = base.join("latest");
+            let temporary = base.join("latest.tmp");
+            let _ = std::fs::remove_file(&temporary);
+            std::os::unix::fs::symlink(absolute(dir), &temporary)?;
+            std::fs::rename(&temporary, &link)
+                .map_err(|err| format!("Cannot update {}: {err}", link.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// `time` as an RFC 3339 UTC timestamp, e.g. `2026-10-16T14:22:33Z`.
+pub fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let (hour, minute, second) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+/// Gregorian date of a day count since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Intervals kept to estimate the device's normal notification period.
+const INTERVAL_WINDOW: usize = 16;
+/// Range a wrist or chest sensor can plausibly report.
+const PLAUSIBLE_BPM: std::ops::RangeInclusive<u16> = 30..=220;
+/// RR intervals outside this range are sensor glitches, not beats.
+const PLAUSIBLE_RR: std::ops::RangeInclusive<Duration> =
+    Duration::from_millis(300)..=Duration::from_millis(2000);
+/// Largest change between successive RR intervals, as a fraction of the
+/// earlier one, before it counts as an ectopic beat or a missed detection.
+const MAX_RR_CHANGE: f64 = 0.2;
+/// How far the heart rate implied by the RR intervals may stray from the
+/// reported one, as a fraction of it.
+const MAX_RR_DISAGREEMENT: f64 = 0.15;
+
+/// Scores each sample 0–100 from sensor contact, plausibility, how regular
+/// notifications arrive and whether its RR intervals back the heart rate up,
+/// so consumers can weight or filter data. One scorer per connection.
+#[derive(Default)]
+pub struct QualityScorer {
+    last_arrival: Option<Instant>,
+    last_bpm: Option<u16>,
+    /// The last plausible RR interval, for the first difference of the next
+    /// notification.
+    last_rr: Option<Duration>,
+    intervals: VecDeque<Duration>,
+}
+
+impl QualityScorer {
+    /// Score a sample that arrived at `received`.
+    pub fn score(
+        &mut self,
+        bpm: u16,
+        contact: Option<bool>,
+        rr_intervals: &[Duration],
+        received: Instant,
+    ) -> u8 {
+        let mut score: i32 = 100;
+
+        // No skin contact: the value is most likely made up by the firmware
+        if contact == Some(false) {
+            score -= 60;
+        }
+
+        if !PLAUSIBLE_BPM.contains(&bpm) {
+            score -= 50;
+        } else if let Some(last) = self.last_bpm {
+            // Beat-to-beat HR cannot change that fast between notifications
+            let jump = bpm.abs_diff(last);
+            if jump > 20 {
+                score -= (jump as i32 - 20).min(30);
+            }
+        }
+
+        score -= self.score_rr(bpm, rr_intervals);
+
+        // Jitter relative to the usual notification period
+        if let Some(last) = self.last_arrival {
+            let interval = received.saturating_duration_since(last);
+            if let Some(typical) = self.typical_interval() {
+                let deviation = interval.abs_diff(typical).as_secs_f32() / typical.as_secs_f32();
+                score -= (deviation * 30.0).min(30.0) as i32;
+            }
+            if self.intervals.len() == INTERVAL_WINDOW {
+                self.intervals.pop_front();
+            }
+            self.intervals.push_back(interval);
+        }
+
+        self.last_arrival = Some(received);
+        self.last_bpm = Some(bpm);
+        score.clamp(0, 100) as u8
+    }
+
+    /// Penalty for RR intervals that are implausible, jump between beats or
+    /// disagree with `bpm`. Zero without RR data.
+    fn score_rr(&mut self, bpm: u16, rr_intervals: &[Duration]) -> i32 {
+        let mut penalty = 0;
+        let (plausible, glitches): (Vec<Duration>, Vec<Duration>) = rr_intervals
+            .iter()
+            .copied()
+            .partition(|rr| PLAUSIBLE_RR.contains(rr));
+        penalty += (glitches.len() as i32 * 15).min(30);
+
+        let mut outliers = 0;
+        for &rr in &plausible {
+            if let Some(last) = self.last_rr {
+                let change = rr.abs_diff(last).as_secs_f64() / last.as_secs_f64();
+                if change > MAX_RR_CHANGE {
+                    outliers += 1;
+                }
+            }
+            self.last_rr = Some(rr);
+        }
+        penalty += (outliers * 10).min(20);
+
+        if !plausible.is_empty() && bpm > 0 {
+            let mean = plausible.iter().sum::<Duration>() / plausible.len() as u32;
+            let implied = 60.0 / mean.as_secs_f64();
+            let disagreement = (implied - f64::from(bpm)).abs() / f64::from(bpm);
+            if disagreement > MAX_RR_DISAGREEMENT {
+                penalty += ((disagreement - MAX_RR_DISAGREEMENT) * 100.0).min(25.0) as i32;
+            }
+        }
+        penalty
+    }
+
+    /// Median of the recent notification intervals.
+    fn typical_interval(&self) -> Option<Duration> {
+        if self.intervals.len() < 3 {
+            return None;
+        }
+        let mut sorted: Vec<_> = self.intervals.iter().copied().collect();
+        sorted.sort();
+        Some(sorted[sorted.len() / 2]).filter(|d| !d.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rr(ms: &[u64]) -> Vec<Duration> {
+        ms.iter().map(|&ms| Duration::from_millis(ms)).collect()
+    }
+
+    #[test]
+    fn consistent_rr_intervals_keep_the_score() {
+        let mut scorer = QualityScorer::default();
+        let now = Instant::now();
+        assert_eq!(scorer.score(75, Some(true), &rr(&[800, 810]), now), 100);
+        assert_eq!(scorer.score(75, Some(true), &[], now), 100);
+    }
+
+    #[test]
+    fn rr_intervals_disagreeing_with_the_heart_rate() {
+        let mut scorer = QualityScorer::default();
+        // 600 ms is 100 bpm, a third above the reported rate
+        let score = scorer.score(75, Some(true), &rr(&[600, 600]), Instant::now());
+        assert_eq!(score, 100 - 18);
+    }
+
+    #[test]
+    fn implausible_and_jumping_rr_intervals() {
+        let mut scorer = QualityScorer::default();
+        let now = Instant::now();
+        assert_eq!(scorer.score(60, Some(true), &rr(&[1000, 3000]), now), 85);
+
+        let mut scorer = QualityScorer::default();
+        // 1000 -> 700 ms is an ectopic beat, and the mean still matches 70 bpm
+        let score = scorer.score(70, Some(true), &rr(&[1000, 700, 1000]), now);
+        assert_eq!(score, 80);
+    }
+}
@@ -0,0 +1,296 @@
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tracing::info;
+
+use crate::export::{write_atomically, ExportFormat, Exporter};
+use crate::locale::EnergyUnit;
+use crate::output::Sample;
+
+/// Appends measurements to a CSV file, one row per notification. After a
+/// system suspend the rows go to a new segment file, `hr-2.csv` after
+/// `hr.csv` and so on.
+pub struct Recorder {
+    writer: BufWriter<std::fs::File>,
+    flush_every: Duration,
+    last_flush: Instant,
+    /// Files of the segments so far, the current one last.
+    files: Vec<PathBuf>,
+    /// Rows in the current segment.
+    rows: u64,
+    energy: EnergyUnit,
+}
+
+/// The CSV header for energy in `energy`.
+fn header(energy: EnergyUnit) -> String {
+    format!(
+        "timestamp_ms,bpm,raw_bpm,contact,energy_{},rr_ms,quality,device",
+        energy.symbol().to_ascii_lowercase()
+    )
+}
+
+/// Open `path` for appending, writing the header if the file is new. An
+/// existing file must have the same columns, or the rows would not line up.
+fn open(path: &Path, energy: EnergyUnit) -> Result<BufWriter<std::fs::File>, Box<dyn Error>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| format!("Cannot open {}: {err}", path.display()))?;
+    let header = header(energy);
+    if file.metadata()?.len() == 0 {
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{header}")?;
+        return Ok(writer);
+    }
+
+    let mut existing = String::new();
+    BufReader::new(&file).read_line(&mut existing)?;
+    let existing = existing.trim_end();
+    if existing != header {
+        return Err(format!(
+            "{} has the columns `{existing}`, not `{header}`; record to a new file \
+             or keep the --energy-unit it was started with",
+            path.display()
+        )
+        .into());
+    }
+    Ok(BufWriter::new(file))
+}
+
+impl Recorder {
+    pub fn create(
+        path: &Path,
+        flush_every: Duration,
+        energy: EnergyUnit,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Recorder {
+            writer: open(path, energy)?,
+            flush_every,
+            last_flush: Instant::now(),
+            files: vec![path.to_owned()],
+            rows: 0,
+            energy,
+        })
+    }
+
+    /// Close the current segment and continue in the next file, unless the
+    /// current one has no rows yet.
+    pub fn next_segment(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        self.flush()?;
+        let first = &self.files[0];
+        let mut name = first.file_stem().unwrap_or_default().to_owned();
+        name.push(format!("-{}", self.files.len() + 1));
+        if let Some(extension) = first.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        let path = first.with_file_name(name);
+        self.writer = open(&path, self.energy)?;
+        info!("Recording to {}", path.display());
+        self.files.push(path);
+        self.rows = 0;
+        Ok(())
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Append `sample`, stamped with `time`.
+    pub fn push(&mut self, sample: &Sample, time: SystemTime) -> Result<(), Box<dyn Error>> {
+        let ts = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        let contact = sample.contact.map_or(String::new(), |c| c.to_string());
+        let energy = sample
+            .energy_expended
+            .map_or(String::new(), |kj| match self.energy {
+                EnergyUnit::Kj => kj.to_string(),
+                EnergyUnit::Kcal => format!("{:.1}", self.energy.convert(f64::from(kj))),
+            });
+        // Space separated so the column stays a single CSV field
+        let rr = sample
+            .rr_intervals
+            .iter()
+            .map(|rr| format!("{:.1}", rr.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(" ");
+        // Names are free text; quote them the CSV way
+        let device = sample
+            .device
+            .as_ref()
+            .map_or(String::new(), |d| format!("\"{}\"", d.replace('"', "\"\"")));
+        // One write per row, so the buffer never holds half a row
+        let row = format!(
+            "{ts},{},{},{contact},{energy},{rr},{},{device}\n",
+            sample.bpm, sample.raw_bpm, sample.quality
+        );
+        self.writer.write_all(row.as_bytes())?;
+        self.rows += 1;
+
+        if self.last_flush.elapsed() >= self.flush_every {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write out buffered rows and sync them to disk, so a crash or power
+    /// loss costs at most the rows since the last flush.
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+}
+
+/// What [`salvage`] kept of a recording.
+struct Salvaged<'a> {
+    header: &'a str,
+    /// Rows that parse, as written.
+    rows: Vec<&'a str>,
+    /// Time and heart rate of each row.
+    points: Vec<(SystemTime, u16)>,
+    /// Rows dropped as cut off or garbled.
+    dropped: usize,
+}
+
+/// Read back a recording, dropping rows an unclean shutdown cut off or
+/// garbled: the last one without its line break, or one filled with zeros
+/// by the file system.
+fn salvage(contents: &str) -> Result<Salvaged<'_>, String> {
+    let mut lines = contents.split_inclusive('\n');
+    let header = lines.next().unwrap_or_default().trim_end();
+    if ![EnergyUnit::Kj, EnergyUnit::Kcal]
+        .into_iter()
+        .any(|energy| header == self::header(energy))
+    {
+        return Err(format!("not a recording, the header is `{header}`"));
+    }
+    let mut salvaged = Salvaged {
+        header,
+        rows: Vec::new(),
+        points: Vec::new(),
+        dropped: 0,
+    };
+    for line in lines {
+        let point = line.strip_suffix('\n').and_then(|row| {
+            let row = row.strip_suffix('\r').unwrap_or(row);
+            // The device name is quoted and may hold commas, so it is the rest
+            let fields: Vec<&str> = row.splitn(8, ',').collect();
+            let [ts, bpm, _raw_bpm, _contact, _energy, _rr, _quality, device] = fields[..] else {
+                return None;
+            };
+            let quoted = device.len() >= 2 && device.starts_with('"') && device.ends_with('"');
+            if !device.is_empty() && !quoted {
+                return None;
+            }
+            let time = UNIX_EPOCH + Duration::from_millis(ts.parse().ok()?);
+            Some((row, (time, bpm.parse().ok()?)))
+        });
+        match point {
+            Some((row, point)) => {
+                salvaged.rows.push(row);
+                salvaged.points.push(point);
+            }
+            None => salvaged.dropped += 1,
+        }
+    }
+    Ok(salvaged)
+}
+
+/// Salvage recordings left behind by a crash or power loss, the segments of
+/// one session in order. Damaged files are rewritten with the rows that
+/// survived, keeping the original next to them as `.bak`; `exports` are
+/// rebuilt from the rows, one lap per file.
+pub fn repair(files: &[PathBuf], exports: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let targets = exports
+        .iter()
+        .map(|path| Ok((path.clone(), ExportFormat::from_path(path)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut exporter = Exporter::new(targets);
+    for path in files {
+        let contents =
+            std::fs::read(path).map_err(|err| format!("Cannot read {}: {err}", path.display()))?;
+        let contents = String::from_utf8_lossy(&contents);
+        let salvaged = salvage(&contents).map_err(|err| format!("{}: {err}", path.display()))?;
+        let rows = salvaged.rows.len();
+        if salvaged.dropped == 0 {
+            println!("{}: {rows} rows, nothing to repair", path.display());
+        } else {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(".bak");
+            let backup = PathBuf::from(backup);
+            std::fs::copy(path, &backup)
+                .map_err(|err| format!("Cannot write {}: {err}", backup.display()))?;
+            let mut repaired = format!("{}\n", salvaged.header);
+            for row in &salvaged.rows {
+                repaired.push_str(row);
+                repaired.push('\n');
+            }
+            write_atomically(path, repaired.as_bytes())?;
+            println!(
+                "{}: kept {rows} rows, dropped {} (original in {})",
+                path.display(),
+                salvaged.dropped,
+                backup.display()
+            );
+        }
+        exporter.next_segment();
+        for (time, bpm) in salvaged.points {
+            exporter.push(time, bpm);
+        }
+    }
+    exporter.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_only_to_a_matching_header() {
+        let path = std::env::temp_dir().join(format!("record-test-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut writer = open(&path, EnergyUnit::Kj).unwrap();
+        writeln!(writer, "1,60,60,,,,100,").unwrap();
+        drop(writer);
+        open(&path, EnergyUnit::Kj).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            contents,
+            format!("{}\n1,60,60,,,,100,\n", header(EnergyUnit::Kj))
+        );
+
+        let err = open(&path, EnergyUnit::Kcal).unwrap_err().to_string();
+        assert!(err.contains("energy_kj"), "{err}");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn salvage_drops_damaged_rows() {
+        let contents = format!(
+            "{}\n1000,60,60,,,,100,\n2000,61,61,true,,,100,\"Band, left\"\n\0\0\0\0\n3000,6",
+            header(EnergyUnit::Kcal)
+        );
+        let salvaged = salvage(&contents).unwrap();
+        assert_eq!(salvaged.rows.len(), 2);
+        assert_eq!(salvaged.dropped, 2);
+        assert_eq!(
+            salvaged.points[1],
+            (UNIX_EPOCH + Duration::from_secs(2), 61)
+        );
+
+        assert!(salvage("time,bpm\n1000,60\n").is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use std::error::Error;
+
+use bluest::error::ErrorKind;
+use miband_heart_rate::timeout::TimeoutError;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// Runs a user-supplied command (e.g. `btmgmt power off && btmgmt power on`)
+/// after repeated adapter failures, for unattended deployments where nobody is
+/// around to toggle Bluetooth.
+pub struct Recovery {
+    command: String,
+    after: u32,
+    failures: u32,
+}
+
+/// Errors that point at the local Bluetooth stack rather than the device.
+fn is_adapter_error(err: &(dyn Error + 'static)) -> bool {
+    if err.is::<TimeoutError>() {
+        return true;
+    }
+    err.downcast_ref::<bluest::Error>().is_some_and(|err| {
+        matches!(
+            err.kind(),
+            ErrorKind::AdapterUnavailable | ErrorKind::Internal
+        )
+    })
+}
+
+impl Recovery {
+    pub fn new(command: String, after: u32) -> Self {
+        Recovery {
+            command,
+            after,
+            failures: 0,
+        }
+    }
+
+    pub fn success(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Count `err` and run the recovery command once enough adapter errors
+    /// happened in a row.
+    pub async fn failure(&mut self, err: &(dyn Error + 'static)) {
+        if !is_adapter_error(err) {
+            return;
+        }
+        self.failures += 1;
+        if self.failures < self.after {
+            return;
+        }
+        self.failures = 0;
+
+        warn!(
+            "{} adapter errors in a row, running recovery: {}",
+            self.after, self.command
+        );
+        match shell(&self.command).status().await {
+            Ok(status) if status.success() => info!("Recovery finished"),
+            Ok(status) => warn!("Recovery command failed: {status}"),
+            Err(err) => warn!("Cannot run recovery command: {err}"),
+        }
+    }
+}
+
+/// `command` run through the platform shell, so pipes and `&&` work.
+pub fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
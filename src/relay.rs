@@ -0,0 +1,125 @@
+use std::error::Error;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::watch;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{info, warn};
+
+/// Wait before reconnecting to the relay after the connection drops.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Publishes the live stream to a relay server under a random channel, so
+/// others can watch through a share link without port forwarding.
+pub struct Relay {
+    tx: watch::Sender<Option<String>>,
+}
+
+impl Relay {
+    /// Start publishing to the relay at `url` (`https://relay.example.com`).
+    /// `key` is the relay's publisher key, if it requires one.
+    pub fn connect(url: &str, key: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let url = url.trim_end_matches('/');
+        let (http, ws) = if let Some(host) = url.strip_prefix("https://") {
+            (url.to_owned(), format!("wss://{host}"))
+        } else if let Some(host) = url.strip_prefix("http://") {
+            (url.to_owned(), format!("ws://{host}"))
+        } else {
+            return Err(format!("Relay URL must start with http:// or https://: {url:?}").into());
+        };
+
+        // The token makes the channel ours: nobody else can publish to it
+        let channel = random_hex(4)?;
+        let token = random_hex(16)?;
+        let target = Target {
+            url: format!("{ws}/publish/{channel}"),
+            token,
+            key,
+        };
+        // Printed directly, as the link is the point of --relay even with -q
+        eprintln!("Share link: {http}/view/{channel}");
+
+        let (tx, mut rx) = watch::channel(None);
+        tokio::spawn(async move {
+            let mut failing = false;
+            loop {
+                match publish(&target, &mut rx, &mut failing).await {
+                    // Nothing left to publish
+                    Ok(()) => return,
+                    Err(err) if !failing => {
+                        warn!("Relay connection failed: {err}");
+                        failing = true;
+                    }
+                    Err(_) => {}
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        });
+
+        Ok(Relay { tx })
+    }
+
+    pub fn send(&self, message: &str) {
+        self.tx.send_replace(Some(message.to_owned()));
+    }
+}
+
+struct Target {
+    url: String,
+    token: String,
+    key: Option<String>,
+}
+
+/// Forward the latest message to the relay until the connection drops.
+async fn publish(
+    target: &Target,
+    rx: &mut watch::Receiver<Option<String>>,
+    failing: &mut bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut request = target.url.as_str().into_client_request()?;
+    let headers = request.headers_mut();
+    headers.insert(AUTHORIZATION, format!("Bearer {}", target.token).parse()?);
+    if let Some(key) = &target.key {
+        headers.insert("x-relay-key", key.parse()?);
+    }
+
+    let (stream, _) = connect_async(request).await?;
+    if *failing {
+        info!("Relay reconnected");
+        *failing = false;
+    }
+    let (mut sink, mut source) = stream.split();
+    // Resend the latest value so the relay can hand it to new viewers
+    rx.mark_changed();
+    loop {
+        tokio::select! {
+            changed = rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+                let message = rx.borrow_and_update().clone();
+                if let Some(message) = message {
+                    sink.send(Message::text(message)).await?;
+                }
+            }
+            incoming = source.next() => match incoming {
+                Some(Ok(Message::Close(_))) | None => {
+                    return Err("relay closed the connection".into());
+                }
+                Some(Err(err)) => return Err(err.into()),
+                // Pongs are queued by tungstenite and flushed with the next send
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// `bytes` bytes from the OS's secure random source, as hex.
+fn random_hex(bytes: usize) -> Result<String, Box<dyn Error>> {
+    let mut buf = vec![0; bytes];
+    getrandom::fill(&mut buf).map_err(|err| format!("Cannot generate a relay token: {err}"))?;
+    Ok(buf.iter().map(|byte| format!("{byte:02x}")).collect())
+}
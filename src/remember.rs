@@ -0,0 +1,35 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use bluest::DeviceId;
+
+/// Where the identifier of the last streamed device is kept. On Apple platforms
+/// this is the CoreBluetooth peripheral UUID, which survives address rotation.
+fn path() -> Option<PathBuf> {
+    Some(
+        dirs::data_local_dir()?
+            .join("miband-heart-rate")
+            .join("device.json"),
+    )
+}
+
+pub fn load() -> Option<DeviceId> {
+    let data = fs::read(path()?).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+pub fn save(id: &DeviceId) -> Result<(), Box<dyn Error>> {
+    let path = path().ok_or("No data directory on this platform")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_vec(id)?)?;
+    Ok(())
+}
+
+pub fn forget() {
+    if let Some(path) = path() {
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,154 @@
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Release assets are expected to be named `miband-heart-rate-<target>[.exe]`,
+/// each with a `<asset>.sha256` file holding its hex digest.
+const ASSET_PREFIX: &str = "miband-heart-rate-";
+/// Refuse to download anything larger than this.
+const MAX_ASSET_SIZE: u64 = 64 * 1024 * 1024;
+
+fn version(v: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = v.trim_start_matches('v').split(['.', '-']);
+    Some((
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+    ))
+}
+
+fn get(url: &str) -> Result<ureq::Response, Box<dyn Error>> {
+    Ok(ureq::get(url)
+        .set(
+            "User-Agent",
+            concat!("miband-heart-rate/", env!("CARGO_PKG_VERSION")),
+        )
+        .call()?)
+}
+
+fn download(url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    get(url)?
+        .into_reader()
+        .take(MAX_ASSET_SIZE)
+        .read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Replace the running binary with `binary`. It is staged next to the
+/// executable in a file only we created, and the bytes read back through our
+/// handle are checked against `expected` before the staged file takes the
+/// executable's place.
+fn install(binary: &[u8], expected: &str) -> Result<(), Box<dyn Error>> {
+    let exe = std::env::current_exe()?.canonicalize()?;
+    let dir = exe.parent().ok_or("Executable has no directory")?;
+    let mut name = exe.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{}.new", std::process::id()));
+    let staged = dir.join(name);
+
+    let mut options = OpenOptions::new();
+    // Fails on anything already there, symlinks included
+    options.read(true).write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o700);
+    }
+    let mut file = options
+        .open(&staged)
+        .map_err(|err| format!("Cannot create {}: {err}", staged.display()))?;
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        file.write_all(binary)?;
+        file.sync_all()?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut staged_bytes = Vec::new();
+        file.read_to_end(&mut staged_bytes)?;
+        let actual = format!("{:x}", Sha256::digest(&staged_bytes));
+        if actual != expected {
+            return Err(
+                format!("Staged update does not match: expected {expected}, got {actual}").into(),
+            );
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{MetadataExt, PermissionsExt};
+            file.set_permissions(fs::Permissions::from_mode(0o755))?;
+            // The path must still be the file behind our handle
+            let (ours, there) = (file.metadata()?, fs::symlink_metadata(&staged)?);
+            if (ours.dev(), ours.ino()) != (there.dev(), there.ino()) {
+                return Err("Staged update was replaced before installing".into());
+            }
+            // Same directory, so this atomically swaps in the checked file
+            fs::rename(&staged, &exe)?;
+        }
+        #[cfg(not(unix))]
+        self_replace::self_replace(&staged)?;
+        Ok(())
+    })();
+    drop(file);
+    let _ = fs::remove_file(&staged);
+    result
+}
+
+/// Check GitHub for a newer release and, unless `check_only`, replace the
+/// running binary with it after verifying its checksum.
+pub fn run(check_only: bool) -> Result<(), Box<dyn Error>> {
+    let repo = env!("CARGO_PKG_REPOSITORY").trim_start_matches("https://github.com/");
+    let release: Value = serde_json::from_str(
+        &get(&format!(
+            "https://api.github.com/repos/{repo}/releases/latest"
+        ))?
+        .into_string()?,
+    )?;
+    let tag = release["tag_name"]
+        .as_str()
+        .ok_or("Latest release has no tag")?;
+
+    let current = env!("CARGO_PKG_VERSION");
+    if version(tag) <= version(current) {
+        println!("Already up to date ({current})");
+        return Ok(());
+    }
+    println!("Update available: {current} -> {tag}");
+    if check_only {
+        return Ok(());
+    }
+
+    let target = env!("MIBAND_TARGET");
+    let name = format!("{ASSET_PREFIX}{target}{}", std::env::consts::EXE_SUFFIX);
+    let assets = release["assets"]
+        .as_array()
+        .ok_or("Release has no assets")?;
+    let asset_url = |name: &str| {
+        assets
+            .iter()
+            .find(|asset| asset["name"] == name)
+            .and_then(|asset| asset["browser_download_url"].as_str())
+            .map(str::to_owned)
+            .ok_or_else(|| format!("Release {tag} has no asset {name}"))
+    };
+    let binary_url = asset_url(&name)?;
+    let checksum_url = asset_url(&format!("{name}.sha256"))?;
+
+    println!("Downloading {name}");
+    let binary = download(&binary_url)?;
+    let checksum = String::from_utf8(download(&checksum_url)?)?;
+    let expected = checksum
+        .split_whitespace()
+        .next()
+        .ok_or("Empty checksum file")?
+        .to_ascii_lowercase();
+    let actual = format!("{:x}", Sha256::digest(&binary));
+    if actual != expected {
+        return Err(
+            format!("Checksum mismatch for {name}: expected {expected}, got {actual}").into(),
+        );
+    }
+
+    install(&binary, &expected)?;
+    println!("Updated to {tag}");
+    Ok(())
+}
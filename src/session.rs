@@ -0,0 +1,397 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use tokio::sync::watch;
+use tokio::time::{sleep_until, Instant};
+
+use crate::clock::ClockCorrection;
+use crate::locale::Locale;
+use crate::metrics::Totals;
+
+/// Longer silences (e.g. while reconnecting) do not count towards any zone.
+const MAX_GAP: Duration = Duration::from_secs(10);
+
+/// When the session should end, settable from the command line and the HTTP
+/// API.
+pub struct SessionTimer {
+    deadline: watch::Sender<Option<Instant>>,
+}
+
+/// `after` from now, unless that is beyond what the clock can represent.
+fn deadline(after: Duration) -> Result<Instant, String> {
+    Instant::now()
+        .checked_add(after)
+        .ok_or_else(|| format!("Session length {after:?} is too long"))
+}
+
+impl SessionTimer {
+    pub fn new(duration: Option<Duration>) -> Result<Self, String> {
+        let deadline = duration.map(deadline).transpose()?;
+        Ok(SessionTimer {
+            deadline: watch::Sender::new(deadline),
+        })
+    }
+
+    /// End the session `after` from now, replacing any earlier deadline.
+    pub fn stop_after(&self, after: Duration) -> Result<(), String> {
+        self.deadline.send_replace(Some(deadline(after)?));
+        Ok(())
+    }
+
+    /// Wait until the deadline, following changes to it. Never returns without
+    /// one.
+    pub async fn expired(&self) {
+        let mut deadline = self.deadline.subscribe();
+        loop {
+            // Copied out so the borrow is not held while waiting
+            let at = *deadline.borrow_and_update();
+            match at {
+                Some(at) => tokio::select! {
+                    _ = sleep_until(at) => return,
+                    _ = deadline.changed() => {}
+                },
+                // The sender lives in self, so this only wakes on a change
+                None => {
+                    let _ = deadline.changed().await;
+                }
+            }
+        }
+    }
+}
+
+/// Heart rate statistics of one wearer.
+#[derive(Default)]
+struct HeartRateStats {
+    min: Option<u16>,
+    max: Option<u16>,
+    sum: u64,
+    samples: u64,
+    /// Time spent below Z1 and in Z1–Z5.
+    zone_time: [Duration; 6],
+    zones: bool,
+    last: Option<(std::time::Instant, Option<u8>)>,
+    /// Energy expended in kJ, summed over the device's counter increments.
+    energy_kj: Option<u64>,
+    last_energy: Option<u16>,
+}
+
+impl HeartRateStats {
+    fn push(
+        &mut self,
+        bpm: u16,
+        zone: Option<u8>,
+        energy: Option<u16>,
+        received: std::time::Instant,
+    ) {
+        self.min = Some(self.min.map_or(bpm, |min| min.min(bpm)));
+        self.max = Some(self.max.map_or(bpm, |max| max.max(bpm)));
+        self.sum += u64::from(bpm);
+        self.samples += 1;
+
+        // The time since the previous sample was spent in its zone
+        if let Some((at, last_zone)) = self.last {
+            let gap = received.saturating_duration_since(at);
+            if gap <= MAX_GAP {
+                self.zone_time[usize::from(last_zone.unwrap_or(0))] += gap;
+            }
+        }
+        self.zones |= zone.is_some();
+        self.last = Some((received, zone));
+
+        if let Some(energy) = energy {
+            let spent = match self.last_energy {
+                Some(last) if energy >= last => energy - last,
+                // The device reset its counter
+                Some(_) => energy,
+                None => 0,
+            };
+            *self.energy_kj.get_or_insert(0) += u64::from(spent);
+            self.last_energy = Some(energy);
+        }
+    }
+
+    fn summary(&self) -> HeartRateSummary {
+        HeartRateSummary {
+            min: self.min,
+            avg: (self.samples > 0).then(|| self.sum as f64 / self.samples as f64),
+            max: self.max,
+            zone_time: self.zones.then_some(self.zone_time),
+            energy_kj: self.energy_kj,
+        }
+    }
+}
+
+/// Heart rate statistics over the whole session, for the summary printed at
+/// the end. With several devices each wearer is kept apart.
+pub struct SessionStats {
+    started: SystemTime,
+    suspended: Duration,
+    /// By device, `None` when streaming from a single one.
+    wearers: BTreeMap<Option<String>, HeartRateStats>,
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        SessionStats {
+            started: SystemTime::now(),
+            suspended: Duration::ZERO,
+            wearers: BTreeMap::new(),
+        }
+    }
+}
+
+impl SessionStats {
+    pub fn push(
+        &mut self,
+        device: Option<&str>,
+        bpm: u16,
+        zone: Option<u8>,
+        energy: Option<u16>,
+        received: std::time::Instant,
+    ) {
+        self.wearers
+            .entry(device.map(str::to_owned))
+            .or_default()
+            .push(bpm, zone, energy, received);
+    }
+
+    /// Leave a system suspend of `asleep` out of the duration and zone times.
+    pub fn suspended(&mut self, asleep: Duration) {
+        self.suspended += asleep;
+        // The monotonic clock may not have moved while asleep
+        for stats in self.wearers.values_mut() {
+            stats.last = None;
+        }
+    }
+
+    /// The session so far, with the counters kept by the metrics and the
+    /// clock jumps seen while exporting, printed the `locale` way.
+    pub fn summary(
+        &self,
+        totals: &Totals,
+        latency: &[(&'static str, Duration)],
+        corrections: &[ClockCorrection],
+        locale: Locale,
+    ) -> Summary {
+        let ended = SystemTime::now();
+        Summary {
+            started: self.started,
+            ended,
+            duration: ended
+                .duration_since(self.started)
+                .unwrap_or_default()
+                .saturating_sub(self.suspended),
+            suspended: self.suspended,
+            wearers: self
+                .wearers
+                .iter()
+                .map(|(device, stats)| (device.clone(), stats.summary()))
+                .collect(),
+            notifications: totals.notifications,
+            malformed: totals.malformed,
+            dropped_connections: totals.dropped_connections,
+            latency_p99: latency.to_vec(),
+            clock_corrections: corrections.to_vec(),
+            locale,
+        }
+    }
+}
+
+/// The heart rate part of a [`Summary`], for one wearer.
+struct HeartRateSummary {
+    min: Option<u16>,
+    avg: Option<f64>,
+    max: Option<u16>,
+    zone_time: Option<[Duration; 6]>,
+    energy_kj: Option<u64>,
+}
+
+impl HeartRateSummary {
+    fn to_json(&self) -> Value {
+        let zone_s = self.zone_time.map(|time| {
+            json!({
+                "below": time[0].as_secs(),
+                "z1": time[1].as_secs(),
+                "z2": time[2].as_secs(),
+                "z3": time[3].as_secs(),
+                "z4": time[4].as_secs(),
+                "z5": time[5].as_secs(),
+            })
+        });
+        json!({
+            "min_bpm": self.min,
+            "avg_bpm": self.avg.map(|avg| (avg * 10.0).round() / 10.0),
+            "max_bpm": self.max,
+            "zone_s": zone_s,
+            "energy_kj": self.energy_kj,
+        })
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, locale: &Locale, indent: &str) -> fmt::Result {
+        match (self.min, self.avg, self.max) {
+            (Some(min), Some(avg), Some(max)) => writeln!(
+                f,
+                "{indent}Heart rate: min {min}, avg {}, max {max} bpm",
+                locale.decimal(avg, 1)
+            )?,
+            _ => writeln!(f, "{indent}Heart rate: no measurements")?,
+        }
+        if let Some(time) = &self.zone_time {
+            let zones: Vec<String> = (1..=5)
+                .map(|zone| format!("Z{zone} {}", hms(time[zone])))
+                .collect();
+            writeln!(
+                f,
+                "{indent}Time in zone: {} (below Z1 {})",
+                zones.join(", "),
+                hms(time[0])
+            )?;
+        }
+        if let Some(energy) = self.energy_kj {
+            writeln!(f, "{indent}Energy: {}", locale.energy(energy as f64))?;
+        }
+        Ok(())
+    }
+}
+
+/// What a session amounted to, printed on exit and handed to hooks.
+pub struct Summary {
+    started: SystemTime,
+    ended: SystemTime,
+    /// Time awake; `suspended` is left out.
+    duration: Duration,
+    suspended: Duration,
+    /// By device, `None` when streaming from a single one.
+    wearers: Vec<(Option<String>, HeartRateSummary)>,
+    notifications: u64,
+    malformed: u64,
+    dropped_connections: u64,
+    /// By output.
+    latency_p99: Vec<(&'static str, Duration)>,
+    clock_corrections: Vec<ClockCorrection>,
+    locale: Locale,
+}
+
+impl Summary {
+    pub fn to_json(&self) -> Value {
+        let ts = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64)
+        };
+        let mut json = json!({
+            "started_ts": ts(self.started),
+            "ended_ts": ts(self.ended),
+            "duration_s": self.duration.as_secs(),
+            "suspended_s": self.suspended.as_secs(),
+            "notifications": self.notifications,
+            "malformed": self.malformed,
+            "dropped_connections": self.dropped_connections,
+            "latency_p99_ms": self
+                .latency_p99
+                .iter()
+                .map(|&(sink, p99)| {
+                    let ms = (p99.as_secs_f64() * 1_000_000.0).round() / 1000.0;
+                    (sink.to_owned(), ms.into())
+                })
+                .collect::<serde_json::Map<_, _>>(),
+            "clock_corrections": self
+                .clock_corrections
+                .iter()
+                .map(|correction| {
+                    json!({
+                        "ts": correction.ts,
+                        "skew_ms": correction.skew_ms,
+                        "applied": correction.applied,
+                    })
+                })
+                .collect::<Vec<_>>(),
+        });
+        // A single wearer's figures sit at the top level; with several they
+        // are null there and kept in `devices`, keyed by name
+        let none = HeartRateStats::default().summary().to_json();
+        match self.wearers.as_slice() {
+            [(None, wearer)] => merge(&mut json, wearer.to_json()),
+            wearers => {
+                merge(&mut json, none);
+                if !wearers.is_empty() {
+                    json["devices"] = wearers
+                        .iter()
+                        .map(|(device, wearer)| {
+                            (device.clone().unwrap_or_default(), wearer.to_json())
+                        })
+                        .collect::<serde_json::Map<_, _>>()
+                        .into();
+                }
+            }
+        }
+        json
+    }
+}
+
+/// Copy the fields of the `from` object into `into`.
+fn merge(into: &mut Value, from: Value) {
+    if let (Value::Object(into), Value::Object(from)) = (into, from) {
+        into.extend(from);
+    }
+}
+
+/// `1h 02m 03s`, `2m 03s` or `3s`.
+pub fn hms(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s:02}s"),
+        (h, m, s) => format!("{h}h {m:02}m {s:02}s"),
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let locale = &self.locale;
+        writeln!(f, "Session summary:")?;
+        writeln!(
+            f,
+            "  Time: {} – {} UTC",
+            locale.clock(self.started),
+            locale.clock(self.ended)
+        )?;
+        writeln!(f, "  Duration: {}", hms(self.duration))?;
+        if !self.suspended.is_zero() {
+            writeln!(f, "  Suspended: {}", hms(self.suspended))?;
+        }
+        match self.wearers.as_slice() {
+            [] => HeartRateStats::default().summary().write(f, locale, "  ")?,
+            [(None, wearer)] => wearer.write(f, locale, "  ")?,
+            wearers => {
+                for (device, wearer) in wearers {
+                    writeln!(f, "  {}:", device.as_deref().unwrap_or_default())?;
+                    wearer.write(f, locale, "    ")?;
+                }
+            }
+        }
+        writeln!(
+            f,
+            "  Notifications: {} ({} malformed)",
+            self.notifications, self.malformed
+        )?;
+        write!(f, "  Dropped connections: {}", self.dropped_connections)?;
+        if !self.latency_p99.is_empty() {
+            let sinks: Vec<String> = self
+                .latency_p99
+                .iter()
+                .map(|(sink, p99)| format!("{sink} {p99:?}"))
+                .collect();
+            write!(f, "\n  Latency p99: {}", sinks.join(", "))?;
+        }
+        if !self.clock_corrections.is_empty() {
+            write!(
+                f,
+                "\n  Clock jumps corrected: {}",
+                self.clock_corrections.len()
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{watch, OwnedRwLockReadGuard, RwLock};
+use tracing::warn;
+
+/// Coordinates a clean exit. Connections hold a guard while notifications
+/// are on; on shutdown they are told to let go, and the exit waits until they
+/// did.
+pub struct Shutdown {
+    requested: watch::Sender<bool>,
+    connected: Arc<RwLock<()>>,
+}
+
+/// A connection's view of [`Shutdown`].
+#[derive(Clone)]
+pub struct ShutdownWatch {
+    requested: watch::Receiver<bool>,
+    connected: Arc<RwLock<()>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown {
+            requested: watch::Sender::new(false),
+            connected: Arc::default(),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn watch(&self) -> ShutdownWatch {
+        ShutdownWatch {
+            requested: self.requested.subscribe(),
+            connected: self.connected.clone(),
+        }
+    }
+
+    /// Ask connections to let go and wait until they did, at most `grace`.
+    pub async fn request(&self, grace: Duration) {
+        self.requested.send_replace(true);
+        if tokio::time::timeout(grace, self.connected.write())
+            .await
+            .is_err()
+        {
+            warn!("Devices did not let go within {grace:?}, exiting anyway");
+        }
+    }
+}
+
+impl ShutdownWatch {
+    /// Hold off the exit until the guard is dropped. `None` once shutdown
+    /// has begun.
+    pub fn connected(&self) -> Option<OwnedRwLockReadGuard<()>> {
+        if *self.requested.borrow() {
+            return None;
+        }
+        self.connected.clone().try_read_owned().ok()
+    }
+
+    /// Resolves once shutdown has begun.
+    pub async fn requested(&mut self) {
+        let _ = self.requested.wait_for(|&requested| requested).await;
+    }
+}
+
+/// Ctrl-C, or SIGTERM from a service manager or `kill`.
+pub async fn signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => return "Interrupted",
+                _ = terminate.recv() => return "Terminated",
+            }
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+    "Interrupted"
+}
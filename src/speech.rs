@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+use crate::output::Sample;
+
+/// Change since the last announcement that counts as rising or falling.
+const TREND_BPM: u16 = 5;
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+/// `n` in English words, e.g. `one hundred forty two`.
+fn words(n: u32) -> String {
+    match n {
+        0..=19 => ONES[n as usize].to_owned(),
+        20..=99 if n % 10 == 0 => TENS[n as usize / 10].to_owned(),
+        20..=99 => format!("{} {}", TENS[n as usize / 10], ONES[n as usize % 10]),
+        100..=999 if n % 100 == 0 => format!("{} hundred", ONES[n as usize / 100]),
+        100..=999 => format!("{} hundred {}", ONES[n as usize / 100], words(n % 100)),
+        _ if n % 1000 == 0 => format!("{} thousand", words(n / 1000)),
+        _ => format!("{} thousand {}", words(n / 1000), words(n % 1000)),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Verbosity {
+    /// Just the number
+    Brief,
+    /// Heart rate and zone
+    Normal,
+    /// Also the trend, HRV and battery
+    Detailed,
+}
+
+/// What was last said about one device.
+#[derive(Default)]
+struct Heard {
+    /// When the heart rate was last announced, and what it was.
+    last: Option<(Instant, u16)>,
+    zone: Option<u8>,
+    contact: Option<bool>,
+}
+
+/// Plain sentences for screen readers instead of a line per measurement:
+/// the heart rate every so often, and changes of zone, skin contact and
+/// connection as they happen. With several devices each is announced on its
+/// own, named.
+pub struct Announcer {
+    every: Duration,
+    verbosity: Verbosity,
+    heard: HashMap<Option<String>, Heard>,
+}
+
+impl Announcer {
+    pub fn new(every: Duration, verbosity: Verbosity) -> Self {
+        Announcer {
+            every,
+            verbosity,
+            heard: HashMap::new(),
+        }
+    }
+
+    /// What to say about `sample`, if anything.
+    pub fn measurement(&mut self, sample: &Sample) -> Vec<String> {
+        let verbosity = self.verbosity;
+        let every = self.every;
+        let heard = self.heard.entry(sample.device.clone()).or_default();
+        let mut sentences = Vec::new();
+        if sample.contact == Some(false) && heard.contact != Some(false) {
+            sentences.push("No skin contact".to_owned());
+        }
+        heard.contact = sample.contact;
+
+        let zone_changed = sample.zone != heard.zone && heard.last.is_some();
+        if let (true, Some(zone)) = (zone_changed, sample.zone) {
+            sentences.push(format!("Entering zone {}", words(zone.into())));
+        }
+        heard.zone = sample.zone;
+
+        let due = heard
+            .last
+            .is_none_or(|(at, _)| sample.received.duration_since(at) >= every);
+        if due || zone_changed {
+            sentences.push(heart_rate(verbosity, heard.last, sample));
+            heard.last = Some((sample.received, sample.bpm));
+        }
+        match &sample.device {
+            Some(device) => sentences
+                .into_iter()
+                .map(|sentence| format!("{device}: {sentence}"))
+                .collect(),
+            None => sentences,
+        }
+    }
+
+    /// Announce the next measurement of `device` straight away.
+    pub fn disconnected(&mut self, device: Option<&str>) -> String {
+        self.heard.remove(&device.map(str::to_owned));
+        match device {
+            Some(device) => format!("{device} disconnected"),
+            None => "Band disconnected".to_owned(),
+        }
+    }
+}
+
+fn heart_rate(verbosity: Verbosity, last: Option<(Instant, u16)>, sample: &Sample) -> String {
+    let bpm = words(sample.bpm.into());
+    if verbosity == Verbosity::Brief {
+        return bpm;
+    }
+    let mut sentence = format!("Heart rate {bpm}");
+    if let Some(zone) = sample.zone {
+        sentence += &format!(", zone {}", words(zone.into()));
+    }
+    if verbosity < Verbosity::Detailed {
+        return sentence;
+    }
+    match last {
+        Some((_, last)) if sample.bpm >= last.saturating_add(TREND_BPM) => sentence += ", rising",
+        Some((_, last)) if sample.bpm.saturating_add(TREND_BPM) <= last => sentence += ", falling",
+        _ => {}
+    }
+    if let Some(hrv) = &sample.hrv {
+        let rmssd = hrv.rmssd_ms.round() as u32;
+        sentence += &format!(", HRV {} milliseconds", words(rmssd));
+    }
+    if let Some(battery) = sample.battery {
+        sentence += &format!(", battery {} percent", words(battery.into()));
+    }
+    sentence
+}
@@ -0,0 +1,46 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::output::Outputs;
+
+/// How often the clocks are compared.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Unaccounted time above which the machine is taken to have been suspended.
+const MIN_SUSPEND: Duration = Duration::from_secs(30);
+
+/// How long the machine was suspended during a check that took `wall` by the
+/// wall clock and `monotonic` by the monotonic clock, if it was. On Linux and
+/// macOS the monotonic clock stops while suspended; elsewhere the check just
+/// comes back late.
+fn asleep(wall: Duration, monotonic: Duration) -> Option<Duration> {
+    let missing = wall.saturating_sub(monotonic);
+    let late = monotonic.saturating_sub(CHECK_INTERVAL);
+    Some(missing.max(late)).filter(|&asleep| asleep >= MIN_SUSPEND)
+}
+
+/// Watch for system suspends. On resume the outputs start a new segment and
+/// `resumes` is bumped so connections are re-established.
+pub async fn watch(outputs: Arc<Mutex<Outputs>>, resumes: watch::Sender<u32>) {
+    let mut last = (SystemTime::now(), Instant::now());
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        let now = (SystemTime::now(), Instant::now());
+        let wall = now.0.duration_since(last.0).unwrap_or_default();
+        let monotonic = now.1.duration_since(last.1);
+        last = now;
+        let Some(asleep) = asleep(wall, monotonic) else {
+            continue;
+        };
+        info!(
+            "Resumed after about {:.0}s of system suspend, starting a new segment",
+            asleep.as_secs_f32()
+        );
+        if let Err(err) = outputs.lock().unwrap().resumed(asleep) {
+            warn!("Cannot start a new segment: {err}");
+        }
+        resumes.send_modify(|count| *count += 1);
+    }
+}
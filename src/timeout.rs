@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+/// Upper bounds for the BLE calls that are known to hang on some stacks.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub connect: Duration,
+    pub discover: Duration,
+    pub subscribe: Duration,
+    /// A single read or write, like the battery level or the CCCD.
+    pub gatt: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            connect: Duration::from_secs(20),
+            discover: Duration::from_secs(10),
+            subscribe: Duration::from_secs(10),
+            gatt: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Operation {
+    Connect,
+    DiscoverServices,
+    DiscoverCharacteristics,
+    DiscoverDescriptors,
+    Subscribe,
+    Read,
+    Write,
+    /// A step of the Xiaomi auth handshake or heart rate control.
+    Auth,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Connect => "connect",
+            Operation::DiscoverServices => "service discovery",
+            Operation::DiscoverCharacteristics => "characteristic discovery",
+            Operation::DiscoverDescriptors => "descriptor discovery",
+            Operation::Subscribe => "subscribe",
+            Operation::Read => "read",
+            Operation::Write => "write",
+            Operation::Auth => "band control",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct TimeoutError {
+    pub operation: Operation,
+    pub after: Duration,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.operation, self.after)
+    }
+}
+
+impl Error for TimeoutError {}
+
+/// Run `future`, failing with a [`TimeoutError`] for `operation` if it does not
+/// finish within `after`.
+pub async fn timeout<T, E>(
+    operation: Operation,
+    after: Duration,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, Box<dyn Error>>
+where
+    E: Into<Box<dyn Error>>,
+{
+    match tokio::time::timeout(after, future).await {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => Err(TimeoutError { operation, after }.into()),
+    }
+}
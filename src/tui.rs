@@ -0,0 +1,225 @@
+use std::error::Error;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Alignment, Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Sparkline};
+use ratatui::DefaultTerminal;
+use tokio::sync::Notify;
+
+use crate::output::Sample;
+
+/// 3×5 pixel glyphs for 0–9, then `-`.
+const GLYPHS: [[&str; 5]; 11] = [
+    ["###", "# #", "# #", "# #", "###"],
+    ["  #", "  #", "  #", "  #", "  #"],
+    ["###", "  #", "###", "#  ", "###"],
+    ["###", "  #", "###", "  #", "###"],
+    ["# #", "# #", "###", "  #", "  #"],
+    ["###", "#  ", "###", "  #", "###"],
+    ["###", "#  ", "###", "# #", "###"],
+    ["###", "  #", "  #", "  #", "  #"],
+    ["###", "# #", "###", "# #", "###"],
+    ["###", "# #", "###", "  #", "###"],
+    ["   ", "   ", "###", "   ", "   "],
+];
+const DASH: usize = 10;
+/// Most log output kept while the dashboard is open.
+const MAX_HELD: usize = 1024 * 1024;
+
+/// Output meant for stderr while the dashboard owns the terminal, printed
+/// once it is closed. `None` while nothing is held back.
+static HELD: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Writer for log messages: stderr, or held back while the dashboard is open
+/// so they do not scribble over it.
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match HELD.lock().unwrap().as_mut() {
+            Some(held) => {
+                if held.len() + buf.len() <= MAX_HELD {
+                    held.extend_from_slice(buf);
+                }
+                Ok(buf.len())
+            }
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Print `text` on stderr, after the dashboard is closed if it is open.
+pub fn report(text: &str) {
+    let _ = writeln!(LogWriter, "{text}");
+}
+
+/// Zone colors, Z1 to Z5; white without --max-hr.
+fn zone_color(zone: Option<u8>) -> Color {
+    match zone {
+        Some(1) => Color::Gray,
+        Some(2) => Color::Blue,
+        Some(3) => Color::Green,
+        Some(4) => Color::Rgb(255, 150, 0),
+        Some(5) => Color::Red,
+        _ => Color::White,
+    }
+}
+
+/// `bpm` in big block digits, dashes without a value.
+fn big_digits(bpm: Option<u16>) -> Vec<Line<'static>> {
+    let glyphs: Vec<usize> = match bpm {
+        Some(bpm) => bpm
+            .to_string()
+            .bytes()
+            .map(|b| (b - b'0') as usize)
+            .collect(),
+        None => vec![DASH; 3],
+    };
+    (0..5)
+        .map(|row| {
+            let text = glyphs
+                .iter()
+                .map(|&glyph| GLYPHS[glyph][row].replace('#', "██").replace(' ', "  "))
+                .collect::<Vec<_>>()
+                .join("  ");
+            Line::from(text)
+        })
+        .collect()
+}
+
+/// Full-screen terminal dashboard: the current BPM in large digits, a
+/// sparkline of recent values, connection status and battery, colored by
+/// zone.
+pub struct Tui {
+    terminal: DefaultTerminal,
+    bpm: Option<u16>,
+    zone: Option<u8>,
+    battery: Option<u8>,
+    device: Option<String>,
+    recent: Vec<u16>,
+}
+
+impl Tui {
+    /// Take over the terminal. The raw terminal swallows Ctrl-C, so `quit`
+    /// is notified when q, Esc or Ctrl-C is pressed instead.
+    pub fn open(quit: Arc<Notify>) -> Result<Self, Box<dyn Error>> {
+        *HELD.lock().unwrap() = Some(Vec::new());
+        let mut tui = Tui {
+            terminal: ratatui::init(),
+            bpm: None,
+            zone: None,
+            battery: None,
+            device: None,
+            recent: Vec::new(),
+        };
+        std::thread::spawn(move || loop {
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    let ctrl_c = key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                        quit.notify_one();
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        });
+        tui.draw()?;
+        Ok(tui)
+    }
+
+    /// Number of samples the sparkline has room for.
+    pub fn bars(&self) -> usize {
+        self.terminal
+            .size()
+            .map_or(0, |size| usize::from(size.width.saturating_sub(2)))
+    }
+
+    pub fn update(&mut self, sample: &Sample, recent: &[u16]) -> Result<(), Box<dyn Error>> {
+        self.bpm = Some(sample.bpm);
+        self.zone = sample.zone;
+        self.battery = sample.battery;
+        self.device.clone_from(&sample.device);
+        self.recent = recent.to_vec();
+        self.draw()
+    }
+
+    pub fn disconnected(&mut self, recent: &[u16]) -> Result<(), Box<dyn Error>> {
+        self.bpm = None;
+        self.recent = recent.to_vec();
+        self.draw()
+    }
+
+    fn draw(&mut self) -> Result<(), Box<dyn Error>> {
+        let color = zone_color(self.zone);
+        let mut status = match (self.bpm, &self.device) {
+            (Some(_), Some(device)) => format!("● Connected to {device}"),
+            (Some(_), None) => "● Connected".to_owned(),
+            (None, _) => "○ Disconnected, waiting for the band…".to_owned(),
+        };
+        if let Some(battery) = self.battery {
+            status += &format!("   Battery {battery}%");
+        }
+        if let (Some(_), Some(zone)) = (self.bpm, self.zone) {
+            status += &format!("   Z{zone}");
+        }
+        status += "   (q to quit)";
+
+        // Bars relative to the recent range so changes stand out
+        let floor = self
+            .recent
+            .iter()
+            .min()
+            .map_or(0, |min| min.saturating_sub(5));
+        let data: Vec<u64> = self
+            .recent
+            .iter()
+            .map(|&bpm| u64::from(bpm - floor))
+            .collect();
+        let digits = big_digits(self.bpm);
+
+        self.terminal.draw(|frame| {
+            let areas = Layout::vertical([
+                Constraint::Length(1),
+                Constraint::Length(7),
+                Constraint::Min(3),
+            ])
+            .split(frame.area());
+            frame.render_widget(Paragraph::new(status), areas[0]);
+            frame.render_widget(
+                Paragraph::new(digits)
+                    .alignment(Alignment::Center)
+                    .style(Style::default().fg(color))
+                    .block(Block::bordered().title(" BPM ")),
+                areas[1],
+            );
+            frame.render_widget(
+                Sparkline::default()
+                    .block(Block::bordered().title(" Recent "))
+                    .data(&data[..])
+                    .style(Style::default().fg(color)),
+                areas[2],
+            );
+        })?;
+        Ok(())
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        ratatui::restore();
+        if let Some(held) = HELD.lock().unwrap().take() {
+            let _ = io::stderr().write_all(&held);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::alerts::notify;
+use crate::duration::parse_duration;
+use crate::session::hms;
+
+/// Time out of the target zone before the athlete is told to speed up or
+/// ease off, so a zone boundary crossed for a moment stays quiet.
+const DRIFT_GRACE: Duration = Duration::from_secs(10);
+
+/// Hold `zone` for `duration`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub duration: Duration,
+    pub zone: u8,
+}
+
+/// A guided workout, written as steps such as `5m Z2, 4x(3m Z4, 2m Z1)`.
+#[derive(Clone, Debug)]
+pub struct Workout {
+    steps: Vec<Step>,
+}
+
+impl FromStr for Workout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let steps = steps(s)?;
+        if steps.is_empty() {
+            return Err("a workout needs at least one step, e.g. `20m Z2`".into());
+        }
+        Ok(Workout { steps })
+    }
+}
+
+/// Comma separated steps, `DURATION ZONE` or `COUNTx(STEPS)`.
+fn steps(s: &str) -> Result<Vec<Step>, String> {
+    let mut items = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(format!("unmatched `)` in {s:?}")),
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Err(format!("unmatched `(` in {s:?}"));
+    }
+    items.push(&s[start..]);
+
+    let mut steps = Vec::new();
+    for item in items
+        .into_iter()
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        if let Some((count, inner)) = item.strip_suffix(')').and_then(|item| item.split_once('(')) {
+            let count = count.trim().trim_end_matches(['x', 'X']).trim();
+            let count: usize = count
+                .parse()
+                .map_err(|_| format!("invalid repeat count {count:?} in {item:?}"))?;
+            let inner = self::steps(inner)?;
+            for _ in 0..count {
+                steps.extend_from_slice(&inner);
+            }
+            continue;
+        }
+        let (duration, zone) = item
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("expected e.g. `5m Z2`, got {item:?}"))?;
+        let zone = zone.trim().trim_start_matches(['Z', 'z']);
+        let zone = match zone.parse() {
+            Ok(zone @ 1..=5) => zone,
+            _ => return Err(format!("zone must be Z1 to Z5, got {zone:?} in {item:?}")),
+        };
+        steps.push(Step {
+            duration: parse_duration(duration)?,
+            zone,
+        });
+    }
+    Ok(steps)
+}
+
+/// Where the workout stands at some moment.
+pub struct Target {
+    /// Index of the current step.
+    pub index: usize,
+    pub step: Step,
+    /// Time left in the current step.
+    pub left: Duration,
+}
+
+/// Something the athlete should be told.
+pub struct Cue {
+    /// Name of the event on the structured outputs.
+    pub event: &'static str,
+    pub message: String,
+    pub fields: Value,
+    /// Asks for attention: the heart rate left the target zone.
+    pub urgent: bool,
+}
+
+/// Follows a workout through the session: announces each step and cues the
+/// athlete when the heart rate stays out of the step's zone. The clock starts
+/// with the first measurement.
+pub struct Guide {
+    steps: Vec<Step>,
+    notify: bool,
+    started: Option<Instant>,
+    /// Step announced last.
+    step: Option<usize>,
+    /// When the heart rate left the target zone, if it is out.
+    out_since: Option<Instant>,
+    drifting: bool,
+    finished: bool,
+}
+
+impl Guide {
+    /// `notify` shows urgent cues as desktop notifications.
+    pub fn new(workout: Workout, notify: bool) -> Self {
+        Guide {
+            steps: workout.steps,
+            notify,
+            started: None,
+            step: None,
+            out_since: None,
+            drifting: false,
+            finished: false,
+        }
+    }
+
+    /// The current step at `now`, `None` once the workout is over.
+    pub fn target(&self, now: Instant) -> Option<Target> {
+        let mut elapsed = self.started.map_or(Duration::ZERO, |started| {
+            now.saturating_duration_since(started)
+        });
+        for (index, &step) in self.steps.iter().enumerate() {
+            if elapsed < step.duration {
+                let left = step.duration - elapsed;
+                return Some(Target { index, step, left });
+            }
+            elapsed -= step.duration;
+        }
+        None
+    }
+
+    /// Follow a measurement in `zone` (`None` below zone 1) that arrived at
+    /// `received`, and fire the cues it calls for.
+    pub fn measurement(&mut self, zone: Option<u8>, received: Instant) -> Vec<Cue> {
+        if self.finished {
+            return Vec::new();
+        }
+        self.started.get_or_insert(received);
+        let mut cues = Vec::new();
+        let Some(target) = self.target(received) else {
+            self.finished = true;
+            cues.push(Cue {
+                event: "workout_finished",
+                message: "Workout complete".into(),
+                fields: json!({}),
+                urgent: false,
+            });
+            self.fire(&cues);
+            return cues;
+        };
+
+        let step = target.step;
+        if self.step != Some(target.index) {
+            self.step = Some(target.index);
+            self.out_since = None;
+            self.drifting = false;
+            cues.push(Cue {
+                event: "workout_step",
+                message: format!(
+                    "Step {} of {}: Z{} for {}",
+                    target.index + 1,
+                    self.steps.len(),
+                    step.zone,
+                    hms(step.duration)
+                ),
+                fields: json!({
+                    "step": target.index + 1,
+                    "steps": self.steps.len(),
+                    "zone": step.zone,
+                    "duration_s": step.duration.as_secs(),
+                }),
+                urgent: false,
+            });
+        }
+
+        if zone == Some(step.zone) {
+            self.out_since = None;
+            if std::mem::take(&mut self.drifting) {
+                cues.push(Cue {
+                    event: "workout_back",
+                    message: format!("Back in Z{}", step.zone),
+                    fields: json!({ "zone": step.zone }),
+                    urgent: false,
+                });
+            }
+        } else {
+            let out_since = *self.out_since.get_or_insert(received);
+            if !self.drifting && received.saturating_duration_since(out_since) >= DRIFT_GRACE {
+                self.drifting = true;
+                let advice = if zone.is_some_and(|zone| zone > step.zone) {
+                    "ease off"
+                } else {
+                    "speed up"
+                };
+                let now = zone.map_or("below Z1".into(), |zone| format!("in Z{zone}"));
+                cues.push(Cue {
+                    event: "workout_drift",
+                    message: format!("Target Z{}, {now}: {advice}", step.zone),
+                    fields: json!({ "target": step.zone, "zone": zone }),
+                    urgent: true,
+                });
+            }
+        }
+        self.fire(&cues);
+        cues
+    }
+
+    fn fire(&self, cues: &[Cue]) {
+        for cue in cues {
+            if !cue.urgent {
+                info!("Workout: {}", cue.message);
+                continue;
+            }
+            warn!("Workout: {}", cue.message);
+            // The terminal bell, for when nobody is looking at the screen
+            eprint!("\x07");
+            if self.notify {
+                notify("Workout", &cue.message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(minutes: u64, zone: u8) -> Step {
+        Step {
+            duration: Duration::from_secs(minutes * 60),
+            zone,
+        }
+    }
+
+    #[test]
+    fn parses_repeats() {
+        let workout: Workout = "5m Z2, 2x(3m Z4, 2m z1), 90s 3".parse().unwrap();
+        assert_eq!(
+            workout.steps,
+            [
+                step(5, 2),
+                step(3, 4),
+                step(2, 1),
+                step(3, 4),
+                step(2, 1),
+                Step {
+                    duration: Duration::from_secs(90),
+                    zone: 3
+                },
+            ]
+        );
+        assert!("5m Z6".parse::<Workout>().is_err());
+        assert!("5m".parse::<Workout>().is_err());
+        assert!("2x(3m Z4".parse::<Workout>().is_err());
+        assert!("".parse::<Workout>().is_err());
+    }
+
+    #[test]
+    fn cues_steps_and_drift() {
+        let workout: Workout = "1m Z2, 1m Z4".parse().unwrap();
+        let mut guide = Guide::new(workout, false);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let events = |cues: Vec<Cue>| cues.iter().map(|cue| cue.event).collect::<Vec<_>>();
+
+        assert_eq!(events(guide.measurement(Some(2), at(0))), ["workout_step"]);
+        assert!(guide.measurement(Some(3), at(5)).is_empty());
+        let cues = guide.measurement(Some(3), at(15));
+        assert_eq!(cues[0].message, "Target Z2, in Z3: ease off");
+        assert!(guide.measurement(Some(3), at(20)).is_empty());
+        assert_eq!(events(guide.measurement(Some(2), at(25))), ["workout_back"]);
+
+        assert_eq!(events(guide.measurement(None, at(60))), ["workout_step"]);
+        assert_eq!(guide.target(at(70)).unwrap().left, Duration::from_secs(50));
+        let cues = guide.measurement(None, at(70));
+        assert_eq!(cues[0].message, "Target Z4, below Z1: speed up");
+        assert_eq!(
+            events(guide.measurement(Some(4), at(120))),
+            ["workout_finished"]
+        );
+        assert!(guide.measurement(Some(4), at(130)).is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::interval;
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+use tracing::{debug, info, warn};
+
+use crate::latency::Probe;
+
+pub struct WsOptions {
+    /// Send the last message to clients as soon as they connect.
+    pub retain: bool,
+    /// Send a WebSocket ping this often to keep idle connections alive.
+    pub ping: Option<Duration>,
+    /// Record when each message reaches a client.
+    pub latency: Option<Probe>,
+}
+
+/// Broadcasts text messages to every connected WebSocket client.
+pub struct WsServer {
+    tx: broadcast::Sender<(String, Instant)>,
+    last: Arc<Mutex<Option<String>>>,
+}
+
+impl WsServer {
+    pub async fn bind(addr: SocketAddr, options: WsOptions) -> Result<Self, Box<dyn Error>> {
+        let listener = TcpListener::bind(addr).await?;
+        info!(
+            "WebSocket server listening on ws://{}",
+            listener.local_addr()?
+        );
+
+        let (tx, _) = broadcast::channel(64);
+        let last = Arc::new(Mutex::new(None));
+        tokio::spawn(serve(listener, tx.clone(), last.clone(), options));
+        Ok(WsServer { tx, last })
+    }
+
+    /// Send `message` to all clients; `received` is when the underlying
+    /// notification arrived.
+    pub fn send(&self, message: String, received: Instant) {
+        *self.last.lock().unwrap() = Some(message.clone());
+        self.send_transient(message, received);
+    }
+
+    /// Like [`WsServer::send`], but not retained for clients connecting later.
+    pub fn send_transient(&self, message: String, received: Instant) {
+        // No receivers just means nobody is connected
+        let _ = self.tx.send((message, received));
+    }
+}
+
+async fn serve(
+    listener: TcpListener,
+    tx: broadcast::Sender<(String, Instant)>,
+    last: Arc<Mutex<Option<String>>>,
+    options: WsOptions,
+) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!("WebSocket accept failed: {err}");
+                continue;
+            }
+        };
+        let rx = tx.subscribe();
+        let retained = if options.retain {
+            last.lock().unwrap().clone()
+        } else {
+            None
+        };
+        let ping = options.ping;
+        let latency = options.latency.clone();
+        tokio::spawn(async move {
+            if let Err(err) = client(stream, rx, retained, ping, latency).await {
+                debug!("WebSocket client {peer} dropped: {err}");
+            }
+        });
+    }
+}
+
+async fn client(
+    stream: TcpStream,
+    mut rx: broadcast::Receiver<(String, Instant)>,
+    retained: Option<String>,
+    ping: Option<Duration>,
+    latency: Option<Probe>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (mut sink, mut source) = accept_async(stream).await?.split();
+    if let Some(message) = retained {
+        sink.send(Message::text(message)).await?;
+    }
+
+    let pings = ping.is_some();
+    // The fallback period is irrelevant: the branch is disabled without pings
+    let mut ping = interval(ping.unwrap_or(Duration::from_secs(3600)));
+    ping.tick().await;
+    loop {
+        tokio::select! {
+            message = rx.recv() => match message {
+                Ok((message, received)) => {
+                    sink.send(Message::text(message)).await?;
+                    if let Some(latency) = &latency {
+                        latency.delivered(received);
+                    }
+                }
+                // A slow client only misses stale samples
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            incoming = source.next() => match incoming {
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Err(err)) => return Err(err.into()),
+                // Pongs are queued by tungstenite and flushed with the next send
+                Some(Ok(_)) => {}
+            },
+            _ = ping.tick(), if pings => sink.send(Message::Ping(Default::default())).await?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Xiaomi's proprietary authentication (service 0xFEE1) and heart rate
+//! control, for bands that use the AES auth-key scheme. It lets the tool start
+//! continuous measurement itself instead of relying on "Broadcast heart rate"
+//! being enabled in the companion app.
+
+use std::error::Error;
+use std::time::Duration;
+
+use aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+use aes::Aes128;
+use bluest::{btuuid::bluetooth_uuid_from_u16, Characteristic, Device, Uuid};
+use futures_lite::stream::{Stream, StreamExt};
+
+use crate::timeout::{timeout, Operation, Timeouts};
+use crate::HRS_UUID;
+
+const AUTH_SERVICE_UUID: Uuid = bluetooth_uuid_from_u16(0xFEE1);
+const AUTH_UUID: Uuid = Uuid::from_u128(0x00000009_0000_3512_2118_0009af100700);
+/// Heart Rate Control Point.
+const HRCP_UUID: Uuid = bluetooth_uuid_from_u16(0x2A39);
+
+/// Ask for a random number to encrypt, with encrypted auth flags.
+const REQUEST_RANDOM: [u8; 5] = [0x82, 0x00, 0x02, 0x01, 0x00];
+/// Prefix of the encrypted random number.
+const SEND_ENCRYPTED: [u8; 2] = [0x83, 0x00];
+const START_CONTINUOUS: [u8; 3] = [0x15, 0x01, 0x01];
+const STOP_CONTINUOUS: [u8; 3] = [0x15, 0x01, 0x00];
+const KEEP_ALIVE: [u8; 1] = [0x16];
+
+/// How often continuous measurement must be kept alive; the band stops on its
+/// own after about 15 s without it.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(12);
+
+/// Parse a 16 byte auth key written as 32 hex digits, with or without a `0x`
+/// prefix.
+pub fn parse_auth_key(s: &str) -> Result<[u8; 16], String> {
+    let hex = s.trim().trim_start_matches("0x");
+    if hex.len() != 32 || !hex.is_ascii() {
+        return Err(format!("auth key must be 32 hex digits, got {s:?}"));
+    }
+    let mut key = [0; 16];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("auth key is not hex: {s:?}"))?;
+    }
+    Ok(key)
+}
+
+/// Authenticate with the band using its auth key. Each write and reply is
+/// bounded by the GATT timeout, so a band that stops answering fails the
+/// connection instead of hanging it.
+pub async fn authenticate(
+    device: &Device,
+    key: &[u8; 16],
+    timeouts: &Timeouts,
+) -> Result<(), Box<dyn Error>> {
+    let auth = characteristic(device, AUTH_SERVICE_UUID, AUTH_UUID, timeouts).await?;
+    let mut responses = timeout(Operation::Subscribe, timeouts.subscribe, auth.notify()).await?;
+
+    // Challenge
+    timeout(Operation::Auth, timeouts.gatt, auth.write(&REQUEST_RANDOM)).await?;
+    let response = response(&mut responses, timeouts).await?;
+    let random = match response.as_slice() {
+        [0x10, command, 0x01, random @ ..] if command & 0x0F == 0x02 && random.len() == 16 => {
+            random
+        }
+        _ => return Err(format!("Band refused the auth request: {response:02X?}").into()),
+    };
+
+    // Response: the random number encrypted with the key
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut block = GenericArray::clone_from_slice(random);
+    cipher.encrypt_block(&mut block);
+    let encrypted = [&SEND_ENCRYPTED[..], &block[..]].concat();
+    timeout(Operation::Auth, timeouts.gatt, auth.write(&encrypted)).await?;
+    let response = response(&mut responses, timeouts).await?;
+    match response.as_slice() {
+        [0x10, command, 0x01, ..] if command & 0x0F == 0x03 => Ok(()),
+        _ => Err(format!("Authentication failed, wrong auth key? {response:02X?}").into()),
+    }
+}
+
+/// Start continuous heart rate measurement. Call [`keep_alive`] every
+/// [`KEEP_ALIVE_INTERVAL`] afterwards.
+pub async fn start_continuous(device: &Device, timeouts: &Timeouts) -> Result<(), Box<dyn Error>> {
+    let control = characteristic(device, HRS_UUID, HRCP_UUID, timeouts).await?;
+    timeout(Operation::Auth, timeouts.gatt, control.write(&START_CONTINUOUS)).await
+}
+
+/// Stop continuous measurement, so the band does not keep its sensor on
+/// until the keep-alives are missed.
+pub async fn stop_continuous(device: &Device, timeouts: &Timeouts) -> Result<(), Box<dyn Error>> {
+    let control = characteristic(device, HRS_UUID, HRCP_UUID, timeouts).await?;
+    timeout(Operation::Auth, timeouts.gatt, control.write(&STOP_CONTINUOUS)).await
+}
+
+pub async fn keep_alive(device: &Device, timeouts: &Timeouts) -> Result<(), Box<dyn Error>> {
+    let control = characteristic(device, HRS_UUID, HRCP_UUID, timeouts).await?;
+    timeout(Operation::Auth, timeouts.gatt, control.write(&KEEP_ALIVE)).await
+}
+
+/// The band's next auth notification.
+async fn response(
+    responses: &mut (impl Stream<Item = Result<Vec<u8>, bluest::Error>> + Unpin),
+    timeouts: &Timeouts,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let response = timeout(Operation::Auth, timeouts.gatt, async {
+        Ok::<_, Box<dyn Error>>(responses.next().await)
+    })
+    .await?;
+    Ok(response.ok_or("Auth notifications ended")??)
+}
+
+async fn characteristic(
+    device: &Device,
+    service: Uuid,
+    characteristic: Uuid,
+    timeouts: &Timeouts,
+) -> Result<Characteristic, Box<dyn Error>> {
+    let services = timeout(
+        Operation::DiscoverServices,
+        timeouts.discover,
+        device.discover_services_with_uuid(service),
+    )
+    .await?;
+    let service = services
+        .first()
+        .ok_or_else(|| format!("Device has no service {service}"))?;
+    let characteristics = timeout(
+        Operation::DiscoverCharacteristics,
+        timeouts.discover,
+        service.discover_characteristics_with_uuid(characteristic),
+    )
+    .await?;
+    Ok(characteristics
+        .first()
+        .ok_or_else(|| format!("Device has no characteristic {characteristic}"))?
+        .clone())
+}
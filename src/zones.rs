@@ -0,0 +1,50 @@
+/// Lower bound of zones 1 to 5 in percent of the maximum heart rate. Below
+/// the first there is no zone.
+const ZONE_FLOORS: [u16; 5] = [50, 60, 70, 80, 90];
+
+/// Maximum heart rate estimated from age (the classic 220 − age).
+pub fn max_hr_for_age(age: u16) -> u16 {
+    220u16.saturating_sub(age)
+}
+
+/// Heart rate zone of `bpm` (1–5), or `None` below 50 % of `max_hr`.
+pub fn zone(bpm: u16, max_hr: u16) -> Option<u8> {
+    let percent = u32::from(bpm) * 100 / u32::from(max_hr.max(1));
+    ZONE_FLOORS
+        .iter()
+        .rposition(|&floor| percent >= u32::from(floor))
+        .map(|index| index as u8 + 1)
+}
+
+/// The zone moved from one value to another; `None` is below zone 1.
+pub struct ZoneChange {
+    pub from: Option<u8>,
+    pub to: Option<u8>,
+}
+
+/// Follows the zone across the measurements of one connection to report when
+/// it changes.
+pub struct ZoneTracker {
+    max_hr: u16,
+    current: Option<u8>,
+}
+
+impl ZoneTracker {
+    pub fn new(max_hr: u16) -> Self {
+        ZoneTracker {
+            max_hr,
+            current: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<u8> {
+        self.current
+    }
+
+    /// Move to the zone of `bpm`, returning the change if there is one.
+    pub fn update(&mut self, bpm: u16) -> Option<ZoneChange> {
+        let to = zone(bpm, self.max_hr);
+        let from = std::mem::replace(&mut self.current, to);
+        (from != to).then_some(ZoneChange { from, to })
+    }
+}